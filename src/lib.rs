@@ -1,2 +1,3 @@
 mod msgpack;
+mod random;
 mod redis;
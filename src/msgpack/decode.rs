@@ -0,0 +1,319 @@
+//! Decoder of arbitrary msgpack values, for inspection.
+
+use super::Malformed;
+
+/// Arrays and maps nested deeper than this are regarded as malformed
+const MAX_DEPTH: usize = 64;
+
+/// Decoded value along with the name of its format in the msgpack spec, e.g. `int 64`
+#[derive(Debug, PartialEq)]
+pub struct Tagged {
+    pub format: &'static str,
+    pub value: Value,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Value {
+    Nil,
+    Bool(bool),
+    Int(i64),
+    UInt(u64),
+    Float(f64),
+    Str(Vec<u8>),
+    Bin(Vec<u8>),
+    Array(Vec<Tagged>),
+    Map(Vec<(Tagged, Tagged)>),
+    Ext(i8, Vec<u8>),
+}
+
+/// Decode a single msgpack value which must span all the bytes
+pub fn decode(bytes: &[u8]) -> Result<Tagged, Malformed> {
+    let mut decoder = Decoder { bytes, offset: 0 };
+    let value = decoder.value(0)?;
+    if decoder.offset != bytes.len() {
+        return Err(Malformed {
+            offset: decoder.offset,
+        });
+    }
+    Ok(value)
+}
+
+/// Decode an array of integers in any format, which must span all the bytes.
+/// Returns `Ok(None)` if the value is not an array, or an element is not an integer within int64.
+pub fn decode_int_array(bytes: &[u8]) -> Result<Option<Vec<i64>>, Malformed> {
+    let mut decoder = Decoder { bytes, offset: 0 };
+    let len = match decoder.take(1)?[0] {
+        first @ 0x90..=0x9f => (first & 0x0f) as usize,
+        0xdc => decoder.uint(2)? as usize,
+        0xdd => decoder.uint(4)? as usize,
+        _ => return Ok(None),
+    };
+
+    // don't trust the length for preallocation
+    let mut elements = vec![];
+    for _ in 0..len {
+        match decoder.value(1)?.value {
+            Value::Int(n) => elements.push(n),
+            Value::UInt(n) if n <= i64::MAX as u64 => elements.push(n as i64),
+            _ => return Ok(None),
+        }
+    }
+
+    if decoder.offset != bytes.len() {
+        return Err(Malformed {
+            offset: decoder.offset,
+        });
+    }
+    Ok(Some(elements))
+}
+
+struct Decoder<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Decoder<'a> {
+    fn value(&mut self, depth: usize) -> Result<Tagged, Malformed> {
+        let start = self.offset;
+        if depth > MAX_DEPTH {
+            return Err(Malformed { offset: start });
+        }
+
+        let first = self.take(1)?[0];
+        let (format, value) = match first {
+            0x00..=0x7f => ("positive fixint", Value::Int(first as i64)),
+            0x80..=0x8f => ("fixmap", self.map((first & 0x0f) as usize, depth)?),
+            0x90..=0x9f => ("fixarray", self.array((first & 0x0f) as usize, depth)?),
+            0xa0..=0xbf => (
+                "fixstr",
+                Value::Str(self.take((first & 0x1f) as usize)?.to_vec()),
+            ),
+            0xc0 => ("nil", Value::Nil),
+            0xc2 => ("false", Value::Bool(false)),
+            0xc3 => ("true", Value::Bool(true)),
+            0xc4 => ("bin 8", Value::Bin(self.sized(1)?.to_vec())),
+            0xc5 => ("bin 16", Value::Bin(self.sized(2)?.to_vec())),
+            0xc6 => ("bin 32", Value::Bin(self.sized(4)?.to_vec())),
+            0xc7 => ("ext 8", self.ext(1)?),
+            0xc8 => ("ext 16", self.ext(2)?),
+            0xc9 => ("ext 32", self.ext(4)?),
+            0xca => {
+                let bits = self.uint(4)? as u32;
+                ("float 32", Value::Float(f32::from_bits(bits) as f64))
+            }
+            0xcb => ("float 64", Value::Float(f64::from_bits(self.uint(8)?))),
+            0xcc => ("uint 8", Value::UInt(self.uint(1)?)),
+            0xcd => ("uint 16", Value::UInt(self.uint(2)?)),
+            0xce => ("uint 32", Value::UInt(self.uint(4)?)),
+            0xcf => ("uint 64", Value::UInt(self.uint(8)?)),
+            0xd0 => ("int 8", Value::Int(self.uint(1)? as i8 as i64)),
+            0xd1 => ("int 16", Value::Int(self.uint(2)? as i16 as i64)),
+            0xd2 => ("int 32", Value::Int(self.uint(4)? as i32 as i64)),
+            0xd3 => ("int 64", Value::Int(self.uint(8)? as i64)),
+            0xd4 => ("fixext 1", self.fixext(1)?),
+            0xd5 => ("fixext 2", self.fixext(2)?),
+            0xd6 => ("fixext 4", self.fixext(4)?),
+            0xd7 => ("fixext 8", self.fixext(8)?),
+            0xd8 => ("fixext 16", self.fixext(16)?),
+            0xd9 => ("str 8", Value::Str(self.sized(1)?.to_vec())),
+            0xda => ("str 16", Value::Str(self.sized(2)?.to_vec())),
+            0xdb => ("str 32", Value::Str(self.sized(4)?.to_vec())),
+            0xdc => {
+                let len = self.uint(2)? as usize;
+                ("array 16", self.array(len, depth)?)
+            }
+            0xdd => {
+                let len = self.uint(4)? as usize;
+                ("array 32", self.array(len, depth)?)
+            }
+            0xde => {
+                let len = self.uint(2)? as usize;
+                ("map 16", self.map(len, depth)?)
+            }
+            0xdf => {
+                let len = self.uint(4)? as usize;
+                ("map 32", self.map(len, depth)?)
+            }
+            0xe0..=0xff => ("negative fixint", Value::Int(first as i8 as i64)),
+            // 0xc1 is never used
+            _ => return Err(Malformed { offset: start }),
+        };
+
+        Ok(Tagged { format, value })
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], Malformed> {
+        let end = self
+            .offset
+            .checked_add(len)
+            .filter(|&end| end <= self.bytes.len())
+            .ok_or(Malformed {
+                offset: self.bytes.len(),
+            })?;
+        let taken = &self.bytes[self.offset..end];
+        self.offset = end;
+        Ok(taken)
+    }
+
+    /// Read big-endian unsigned integer of `len` bytes
+    fn uint(&mut self, len: usize) -> Result<u64, Malformed> {
+        Ok(self
+            .take(len)?
+            .iter()
+            .fold(0u64, |a, &b| (a << 8) | b as u64))
+    }
+
+    /// Read bytes prefixed by the length of `len_bytes`
+    fn sized(&mut self, len_bytes: usize) -> Result<&'a [u8], Malformed> {
+        let len = self.uint(len_bytes)? as usize;
+        self.take(len)
+    }
+
+    fn ext(&mut self, len_bytes: usize) -> Result<Value, Malformed> {
+        let len = self.uint(len_bytes)? as usize;
+        self.fixext(len)
+    }
+
+    fn fixext(&mut self, len: usize) -> Result<Value, Malformed> {
+        let ext_type = self.take(1)?[0] as i8;
+        Ok(Value::Ext(ext_type, self.take(len)?.to_vec()))
+    }
+
+    fn array(&mut self, len: usize, depth: usize) -> Result<Value, Malformed> {
+        // don't trust the length for preallocation
+        let mut elements = vec![];
+        for _ in 0..len {
+            elements.push(self.value(depth + 1)?);
+        }
+        Ok(Value::Array(elements))
+    }
+
+    fn map(&mut self, len: usize, depth: usize) -> Result<Value, Malformed> {
+        let mut entries = vec![];
+        for _ in 0..len {
+            let key = self.value(depth + 1)?;
+            let value = self.value(depth + 1)?;
+            entries.push((key, value));
+        }
+        Ok(Value::Map(entries))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decode, decode_int_array, Tagged, Value};
+    use crate::msgpack::Malformed;
+
+    fn tagged(format: &'static str, value: Value) -> Tagged {
+        Tagged { format, value }
+    }
+
+    #[test]
+    fn test_decode_scalar() {
+        assert_eq!(
+            decode(&[0x05]),
+            Ok(tagged("positive fixint", Value::Int(5)))
+        );
+        assert_eq!(
+            decode(&[0xff]),
+            Ok(tagged("negative fixint", Value::Int(-1)))
+        );
+        assert_eq!(decode(&[0xc0]), Ok(tagged("nil", Value::Nil)));
+        assert_eq!(decode(&[0xc3]), Ok(tagged("true", Value::Bool(true))));
+        assert_eq!(
+            decode(&[0xd3, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xfe]),
+            Ok(tagged("int 64", Value::Int(-2)))
+        );
+        assert_eq!(
+            decode(&[0xd2, 0x80, 0, 0, 0]),
+            Ok(tagged("int 32", Value::Int(i32::MIN as i64)))
+        );
+        assert_eq!(
+            decode(&[0xcf, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff]),
+            Ok(tagged("uint 64", Value::UInt(u64::MAX)))
+        );
+        assert_eq!(
+            decode(&[0xcb, 0x3f, 0xf8, 0, 0, 0, 0, 0, 0]),
+            Ok(tagged("float 64", Value::Float(1.5)))
+        );
+        assert_eq!(
+            decode(&[0xa2, b'h', b'i']),
+            Ok(tagged("fixstr", Value::Str(b"hi".to_vec())))
+        );
+        assert_eq!(
+            decode(&[0xc4, 1, 0xab]),
+            Ok(tagged("bin 8", Value::Bin(vec![0xab])))
+        );
+        assert_eq!(
+            decode(&[0xd4, 0xff, 7]),
+            Ok(tagged("fixext 1", Value::Ext(-1, vec![7])))
+        );
+    }
+
+    #[test]
+    fn test_decode_nested() {
+        assert_eq!(
+            decode(&[0x92, 0x01, 0x81, 0xa1, b'k', 0x90]),
+            Ok(tagged(
+                "fixarray",
+                Value::Array(vec![
+                    tagged("positive fixint", Value::Int(1)),
+                    tagged(
+                        "fixmap",
+                        Value::Map(vec![(
+                            tagged("fixstr", Value::Str(b"k".to_vec())),
+                            tagged("fixarray", Value::Array(vec![])),
+                        )])
+                    ),
+                ])
+            ))
+        );
+        assert_eq!(
+            decode(&[0xdc, 0, 1, 0xc2]),
+            Ok(tagged(
+                "array 16",
+                Value::Array(vec![tagged("false", Value::Bool(false))])
+            ))
+        );
+    }
+
+    #[test]
+    fn test_decode_malformed() {
+        assert_eq!(decode(&[]), Err(Malformed { offset: 0 }));
+        assert_eq!(decode(&[0xc1]), Err(Malformed { offset: 0 }));
+        // truncated
+        assert_eq!(decode(&[0x92, 0x01]), Err(Malformed { offset: 2 }));
+        assert_eq!(decode(&[0xd3, 0, 0]), Err(Malformed { offset: 3 }));
+        assert_eq!(
+            decode(&[0xdd, 0xff, 0xff, 0xff, 0xff]),
+            Err(Malformed { offset: 5 })
+        );
+        // trailing bytes
+        assert_eq!(decode(&[0x01, 0x02]), Err(Malformed { offset: 1 }));
+        // too deep
+        assert_eq!(decode(&[0x91; 100]), Err(Malformed { offset: 65 }));
+    }
+
+    #[test]
+    fn test_decode_int_array() {
+        assert_eq!(
+            decode_int_array(&[0x94, 0x01, 0xff, 0xcd, 0x01, 0x00, 0xd2, 0x80, 0, 0, 0]),
+            Ok(Some(vec![1, -1, 256, i32::MIN as i64]))
+        );
+        assert_eq!(decode_int_array(&[0xdc, 0, 0]), Ok(Some(vec![])));
+        assert_eq!(
+            decode_int_array(&[0x91, 0xcf, 0x80, 0, 0, 0, 0, 0, 0, 0]),
+            Ok(None)
+        );
+        assert_eq!(decode_int_array(&[0x91, 0xc0]), Ok(None));
+        assert_eq!(decode_int_array(&[0x01]), Ok(None));
+        assert_eq!(
+            decode_int_array(&[0x92, 0x01]),
+            Err(Malformed { offset: 2 })
+        );
+        assert_eq!(
+            decode_int_array(&[0x91, 0x01, 0x02]),
+            Err(Malformed { offset: 2 })
+        );
+    }
+}
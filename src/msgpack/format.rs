@@ -1,4 +1,4 @@
-use super::ByteVector;
+use super::{ByteSlice, ByteVector};
 use std::mem::size_of;
 
 /// Represents msgpack primitive
@@ -9,19 +9,19 @@ where
     const FIRST_BYTE: u8;
     const SIZE: usize;
 
-    fn read<T: ByteVector>(bytes: &T, from: usize) -> Option<Self>;
+    fn read<T: ByteSlice>(bytes: &T, from: usize) -> Option<Self>;
 
     fn write<T: ByteVector>(bytes: &mut T, from: usize, value: Self);
 }
 
-#[derive(Debug, Ord, PartialOrd, Eq, PartialEq)]
+#[derive(Debug, Clone, Copy, Ord, PartialOrd, Eq, PartialEq)]
 pub struct Int64(pub i64);
 
 impl Primitive for Int64 {
     const FIRST_BYTE: u8 = 0xd3;
     const SIZE: usize = size_of::<i64>();
 
-    fn read<T: ByteVector>(bytes: &T, from: usize) -> Option<Self> {
+    fn read<T: ByteSlice>(bytes: &T, from: usize) -> Option<Self> {
         if bytes[from] != Self::FIRST_BYTE {
             None
         } else {
@@ -41,3 +41,31 @@ impl Primitive for Int64 {
         }
     }
 }
+
+#[derive(Debug, Clone, Copy, Ord, PartialOrd, Eq, PartialEq)]
+pub struct Int32(pub i32);
+
+impl Primitive for Int32 {
+    const FIRST_BYTE: u8 = 0xd2;
+    const SIZE: usize = size_of::<i32>();
+
+    fn read<T: ByteSlice>(bytes: &T, from: usize) -> Option<Self> {
+        if bytes[from] != Self::FIRST_BYTE {
+            None
+        } else {
+            let n = (0..Self::SIZE).fold(0i32, |a, i| {
+                a | (bytes[from + i + 1] as i32) << ((Self::SIZE - 1 - i) * 8) as i32
+            });
+            Some(Int32(n))
+        }
+    }
+
+    fn write<T: ByteVector>(bytes: &mut T, from: usize, value: Self) {
+        bytes[from] = Self::FIRST_BYTE;
+
+        let Self(n) = value;
+        for i in 0..Self::SIZE {
+            bytes[from + i + 1] = ((n >> ((Self::SIZE - 1 - i) as i32 * 8)) & 0xff) as u8;
+        }
+    }
+}
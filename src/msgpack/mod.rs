@@ -1,18 +1,22 @@
+pub mod decode;
 pub mod format;
 
 use format::*;
 use std::marker::PhantomData;
 use std::ops::{Index, IndexMut};
 
+/// Abstraction layer for read-only byte array
+pub trait ByteSlice: Index<usize, Output = u8> {
+    fn len(&self) -> usize;
+}
+
 /// Abstraction layer for resizable byte array
-pub trait ByteVector: Index<usize, Output = u8> + IndexMut<usize>
+pub trait ByteVector: ByteSlice + IndexMut<usize>
 where
     Self: Sized,
 {
     type AllocErr;
 
-    fn len(&self) -> usize;
-
     fn memmove(&mut self, dest: usize, src: usize, len: usize);
 
     fn realloc(&self, len: usize) -> Result<Self, Self::AllocErr>;
@@ -21,13 +25,19 @@ where
 /// Represents msgpack array of single type elements
 pub struct MsgpackArray<T, U>
 where
-    T: ByteVector,
+    T: ByteSlice,
     U: Primitive,
 {
     underlying: T,
     element_type: PhantomData<U>,
 }
 
+/// Bytes deviate from msgpack array format at the offset
+#[derive(Debug, Eq, PartialEq)]
+pub struct Malformed {
+    pub offset: usize,
+}
+
 #[derive(Debug, Eq, PartialEq)]
 pub enum ArrayHeader {
     Fix(usize),
@@ -37,10 +47,10 @@ pub enum ArrayHeader {
 
 impl ArrayHeader {
     pub fn len(&self) -> usize {
-        match self {
-            &ArrayHeader::Fix(n) => n,
-            &ArrayHeader::Array16(n) => n,
-            &ArrayHeader::Array32(n) => n,
+        match *self {
+            ArrayHeader::Fix(n) => n,
+            ArrayHeader::Array16(n) => n,
+            ArrayHeader::Array32(n) => n,
         }
     }
 
@@ -52,6 +62,33 @@ impl ArrayHeader {
         }
     }
 
+    /// Parse array header at the beginning of the bytes
+    pub fn parse<T: ByteSlice>(bytes: &T) -> Option<Self> {
+        if bytes.len() < 1 {
+            return None;
+        }
+        match bytes[0] {
+            b @ (0x90..=0x9f) => Some(ArrayHeader::Fix((b - 0x90) as usize)),
+            0xdc => {
+                if bytes.len() < 3 {
+                    return None;
+                }
+                Some(ArrayHeader::Array16((0..2usize).fold(0, |a, i| {
+                    a | ((bytes[1 + i] as usize) << ((1 - i) * 8))
+                })))
+            }
+            0xdd => {
+                if bytes.len() < 5 {
+                    return None;
+                }
+                Some(ArrayHeader::Array32((0..4usize).fold(0, |a, i| {
+                    a | ((bytes[1 + i] as usize) << ((3 - i) * 8))
+                })))
+            }
+            _ => None,
+        }
+    }
+
     pub fn from_len(len: usize) -> Self {
         match len {
             0..=15 => ArrayHeader::Fix(len),
@@ -69,17 +106,17 @@ impl ArrayHeader {
     }
 
     pub fn total_bytes<U: Primitive>(&self) -> usize {
-        match self {
-            &ArrayHeader::Fix(n) => n * (U::SIZE + 1) + 1,
-            &ArrayHeader::Array16(n) => n * (U::SIZE + 1) + 2 + 1,
-            &ArrayHeader::Array32(n) => n * (U::SIZE + 1) + 4 + 1,
+        match *self {
+            ArrayHeader::Fix(n) => n * (U::SIZE + 1) + 1,
+            ArrayHeader::Array16(n) => n * (U::SIZE + 1) + 2 + 1,
+            ArrayHeader::Array32(n) => n * (U::SIZE + 1) + 4 + 1,
         }
     }
 }
 
 impl<T, U> MsgpackArray<T, U>
 where
-    T: ByteVector,
+    T: ByteSlice,
     U: Primitive,
 {
     pub fn header(&self) -> ArrayHeader {
@@ -94,6 +131,115 @@ where
         }
     }
 
+    /// Returns the element at the index, or `Ok(None)` if the index is out of range
+    pub fn get(&self, index: usize) -> Result<Option<U>, Malformed> {
+        if self.header().len() <= index {
+            return Ok(None);
+        }
+
+        let offset = self.header().byte_offset::<U>(index);
+        U::read(&self.underlying, offset)
+            .map(Some)
+            .ok_or(Malformed { offset })
+    }
+
+    /// Returns the element at given quantile (0.0 to 1.0) by nearest-rank method
+    pub fn quantile(&self, q: f64) -> Result<Option<U>, Malformed> {
+        let len = self.header().len();
+        if len < 1 || !(0.0..=1.0).contains(&q) {
+            return Ok(None);
+        }
+
+        let rank = (q * len as f64).ceil() as usize;
+        self.get(rank.max(1).min(len) - 1)
+    }
+
+    /// Parse bytes as array, checking the header and the total length.
+    /// Elements are checked lazily on read.
+    pub fn parse(underlying: T) -> Result<Self, Malformed> {
+        let len = ArrayHeader::parse(&underlying)
+            .ok_or(Malformed { offset: 0 })?
+            .len();
+
+        let total_bytes = ArrayHeader::from_len(len).total_bytes::<U>();
+        if underlying.len() == total_bytes {
+            Ok(Self {
+                underlying,
+                element_type: PhantomData,
+            })
+        } else {
+            Err(Malformed {
+                offset: underlying.len().min(total_bytes),
+            })
+        }
+    }
+
+    pub fn into_inner(self) -> T {
+        self.underlying
+    }
+
+    pub fn binarysearch(&self, element: U) -> Result<SearchResult, Malformed> {
+        let len = self.header().len();
+        if len < 1 {
+            Ok(SearchResult::NotFound(0))
+        } else {
+            self._binarysearch(element, 0, len)
+        }
+    }
+
+    /// Returns the index of the first element which is not less than given element
+    pub fn lower_bound(&self, element: U) -> Result<usize, Malformed> {
+        Ok(match self.binarysearch(element)? {
+            SearchResult::Found(idx) => idx,
+            SearchResult::NotFound(idx) => idx,
+        })
+    }
+
+    /// Returns the index of the first element which is greater than given element
+    pub fn upper_bound(&self, element: U) -> Result<usize, Malformed> {
+        Ok(match self.binarysearch(element)? {
+            SearchResult::Found(idx) => idx + 1,
+            SearchResult::NotFound(idx) => idx,
+        })
+    }
+
+    fn _binarysearch(
+        &self,
+        element: U,
+        start: usize,
+        size: usize,
+    ) -> Result<SearchResult, Malformed> {
+        if size > 1 {
+            let half = size / 2;
+            let mid = start + half;
+
+            let e = self.element_at(mid)?;
+            let next_start = if element < e { start } else { mid };
+            self._binarysearch(element, next_start, size - half)
+        } else {
+            let e = self.element_at(start)?;
+            if element == e {
+                Ok(SearchResult::Found(start))
+            } else {
+                Ok(SearchResult::NotFound(
+                    start + if element > e { 1 } else { 0 },
+                ))
+            }
+        }
+    }
+
+    /// Read the element at the index, which must be in range
+    fn element_at(&self, index: usize) -> Result<U, Malformed> {
+        let offset = self.header().byte_offset::<U>(index);
+        U::read(&self.underlying, offset).ok_or(Malformed { offset })
+    }
+}
+
+impl<T, U> MsgpackArray<T, U>
+where
+    T: ByteVector,
+    U: Primitive,
+{
     pub fn delete_at(&mut self, index: usize) -> Result<(), T::AllocErr> {
         let current_header = self.header();
 
@@ -127,43 +273,87 @@ where
         Ok(())
     }
 
-    pub fn insert_at(&mut self, index: usize, element: U) -> Result<(), T::AllocErr> {
-        let new_len = self.header().len() + 1;
-
+    /// Delete elements in [start, end) and returns the number of deleted elements.
+    ///
+    /// Unlike repeating `delete_at`, remaining elements are moved at once.
+    pub fn delete_range(&mut self, start: usize, end: usize) -> Result<usize, T::AllocErr> {
         let current_header = self.header();
-        let new_header = ArrayHeader::from_len(new_len);
+        let end = end.min(current_header.len());
 
-        let new_bytes = new_header.total_bytes::<U>();
-        self.underlying = self.underlying.realloc(new_bytes)?;
+        if start >= end {
+            // early return if range is empty
+            return Ok(0);
+        }
+
+        let deleted = end - start;
+        let new_header = ArrayHeader::from_len(current_header.len() - deleted);
 
-        if current_header.header_bytes() != new_header.header_bytes() && current_header.len() > 0 {
+        if current_header.header_bytes() != new_header.header_bytes() && start > 0 {
             self.underlying.memmove(
                 new_header.header_bytes(),
                 current_header.header_bytes(),
-                current_header.total_bytes::<U>(),
+                start * (U::SIZE + 1),
             );
         }
-
-        if index < current_header.len() {
+        if end < current_header.len() {
             self.underlying.memmove(
-                new_header.header_bytes() + (index + 1) * (U::SIZE + 1),
-                new_header.header_bytes() + index * (U::SIZE + 1),
-                (current_header.len() - index) * (U::SIZE + 1),
+                new_header.header_bytes() + start * (U::SIZE + 1),
+                current_header.header_bytes() + end * (U::SIZE + 1),
+                (current_header.len() - end) * (U::SIZE + 1),
             );
         }
 
+        self.underlying = self.underlying.realloc(new_header.total_bytes::<U>())?;
         self.write_header(new_header);
-        self.set(index, element);
 
-        Ok(())
+        Ok(deleted)
     }
 
-    pub fn get(&self, index: usize) -> Option<U> {
-        if self.header().len() <= index {
-            return None;
+    /// Insert multiple elements, growing the buffer only once.
+    ///
+    /// Each pair is the index in the current array to insert before, and the element.
+    /// Pairs must be sorted by the index, and by the element for the same index.
+    pub fn insert_many(&mut self, elements: &[(usize, U)]) -> Result<(), T::AllocErr>
+    where
+        U: Copy,
+    {
+        if elements.is_empty() {
+            return Ok(());
+        }
+
+        let current_header = self.header();
+        let new_header = ArrayHeader::from_len(current_header.len() + elements.len());
+
+        let new_bytes = new_header.total_bytes::<U>();
+        self.underlying = self.underlying.realloc(new_bytes)?;
+
+        // shift runs of existing elements from the last one not to overwrite unmoved ones
+        let element_bytes = U::SIZE + 1;
+        let mut end = current_header.len();
+        for (shift, &(index, _)) in elements.iter().enumerate().rev() {
+            if index < end {
+                self.underlying.memmove(
+                    new_header.byte_offset::<U>(index + shift + 1),
+                    current_header.byte_offset::<U>(index),
+                    (end - index) * element_bytes,
+                );
+                end = index;
+            }
+        }
+        if end > 0 && current_header.header_bytes() != new_header.header_bytes() {
+            self.underlying.memmove(
+                new_header.header_bytes(),
+                current_header.header_bytes(),
+                end * element_bytes,
+            );
         }
 
-        U::read(&self.underlying, self.header().byte_offset::<U>(index))
+        self.write_header(new_header);
+        for (shift, &(index, element)) in elements.iter().enumerate() {
+            self.set(index + shift, element);
+        }
+
+        Ok(())
     }
 
     pub fn set(&mut self, index: usize, value: U) {
@@ -188,48 +378,25 @@ where
         })
     }
 
-    pub fn parse(underlying: T) -> Option<Self> {
-        if underlying.len() < 1 {
-            return None;
-        }
-        let len = match underlying[0] {
-            b @ (0x90..=0x9f) => (b - 0x90) as usize,
-            0xdc => {
-                if underlying.len() < 3 {
-                    return None;
-                }
-                (0..2usize).fold(0, |a, i| {
-                    a | ((underlying[1 + i] as usize) << ((1 - i) * 8))
-                })
-            }
-            0xdd => {
-                if underlying.len() < 5 {
-                    return None;
-                }
-                (0..4usize).fold(0, |a, i| {
-                    a | ((underlying[1 + i] as usize) << ((3 - i) * 8))
-                })
-            }
-            _ => return None,
+    /// Initialize array with given elements at once.
+    /// Elements must be sorted in ascending order without duplicates.
+    pub fn with_elements<F>(allocator: F, elements: &[U]) -> Result<Self, T::AllocErr>
+    where
+        F: FnOnce(usize) -> Result<T, T::AllocErr>,
+        U: Copy,
+    {
+        let header = ArrayHeader::from_len(elements.len());
+        let mut array = Self {
+            underlying: allocator(header.total_bytes::<U>())?,
+            element_type: PhantomData,
         };
 
-        if underlying.len() == ArrayHeader::from_len(len).total_bytes::<U>() {
-            Some(Self {
-                underlying,
-                element_type: PhantomData,
-            })
-        } else {
-            None
+        array.write_header(header);
+        for (i, e) in elements.iter().enumerate() {
+            array.set(i, *e);
         }
-    }
 
-    pub fn binarysearch(&self, element: U) -> SearchResult {
-        let len = self.header().len();
-        if len < 1 {
-            SearchResult::NotFound(0)
-        } else {
-            self._binarysearch(element, 0, len)
-        }
+        Ok(array)
     }
 
     fn write_header(&mut self, header: ArrayHeader) {
@@ -249,34 +416,18 @@ where
             }
         }
     }
+}
 
-    fn _binarysearch(&self, element: U, start: usize, size: usize) -> SearchResult {
-        if size > 1 {
-            let half = size / 2;
-            let mid = start + half;
-
-            let e = self.get(mid).unwrap();
-            let next_start = if element < e { start } else { mid };
-            self._binarysearch(element, next_start, size - half)
-        } else {
-            let e = self.get(start).unwrap();
-            if element == e {
-                SearchResult::Found(start)
-            } else {
-                SearchResult::NotFound(start + if element > e { 1 } else { 0 })
-            }
-        }
+impl ByteSlice for Vec<u8> {
+    fn len(&self) -> usize {
+        Vec::len(self)
     }
 }
 
-/// Vec based ByteVector impl. For unit testing purpose only.
+/// Vec based ByteVector impl. Used for unit testing and validating bytes out of Redis keys.
 impl ByteVector for Vec<u8> {
     type AllocErr = ();
 
-    fn len(&self) -> usize {
-        Vec::len(self)
-    }
-
     fn memmove(&mut self, dest: usize, src: usize, len: usize) {
         if dest > src {
             for i in (0..len).rev() {
@@ -313,6 +464,7 @@ pub enum SearchResult {
 mod tests {
     use super::format::*;
     use super::ArrayHeader;
+    use super::Malformed;
     use super::MsgpackArray;
     use super::SearchResult::*;
 
@@ -324,32 +476,130 @@ mod tests {
         assert_eq!(arr.underlying[0], 0x90);
     }
 
+    #[test]
+    fn test_with_elements() {
+        let arr: MsgpackArray<Vec<u8>, Int64> =
+            MsgpackArray::with_elements(|len| Ok(vec![0u8; len]), &[]).unwrap();
+        assert_eq!(arr.underlying, vec![0x90]);
+
+        let elements = (0..20).map(Int64).collect::<Vec<_>>();
+        let arr: MsgpackArray<Vec<u8>, Int64> =
+            MsgpackArray::with_elements(|len| Ok(vec![0u8; len]), &elements).unwrap();
+        assert_eq!(arr.header(), ArrayHeader::Array16(20));
+        assert_eq!(arr.underlying.len(), 20 * 9 + 3);
+        assert_eq!(arr.get(0), Ok(Some(Int64(0))));
+        assert_eq!(arr.get(19), Ok(Some(Int64(19))));
+        assert_eq!(arr.binarysearch(Int64(7)), Ok(Found(7)));
+    }
+
     #[test]
     fn test_parse() {
-        let result: Option<MsgpackArray<Vec<u8>, Int64>> = MsgpackArray::parse(
+        let result: Result<MsgpackArray<Vec<u8>, Int64>, _> = MsgpackArray::parse(
             MsgpackArray::<Vec<u8>, Int64>::new(|len| Ok(vec![0u8; len]))
                 .unwrap()
                 .underlying,
         );
 
-        assert_eq!(result.is_some(), true);
+        assert!(result.is_ok());
         assert_eq!(result.unwrap().header(), ArrayHeader::Fix(0));
 
         let v = vec![
             0x92u8, 0xd3, 0, 0, 0, 0, 0, 0, 0, 0, 0xd3, 0, 0, 0, 0, 0, 0, 0, 0,
         ];
-        let result: Option<MsgpackArray<Vec<u8>, Int64>> = MsgpackArray::parse(v);
+        let result: Result<MsgpackArray<Vec<u8>, Int64>, _> = MsgpackArray::parse(v);
 
-        assert_eq!(result.is_some(), true);
+        assert!(result.is_ok());
         assert_eq!(result.unwrap().header(), ArrayHeader::Fix(2));
 
+        assert!(MsgpackArray::<Vec<u8>, Int64>::parse(vec![0u8; 5]).is_err());
+    }
+
+    #[test]
+    fn test_parse_malformed() {
+        // unknown header
+        let result = MsgpackArray::<Vec<u8>, Int64>::parse(vec![0xc0u8, 0, 0]);
+        assert_eq!(result.err(), Some(Malformed { offset: 0 }));
+
+        // truncated header
+        let result = MsgpackArray::<Vec<u8>, Int64>::parse(vec![0xdcu8, 0]);
+        assert_eq!(result.err(), Some(Malformed { offset: 0 }));
+
+        // truncated element
+        let result = MsgpackArray::<Vec<u8>, Int64>::parse(vec![0x91u8, 0xd3, 0, 0]);
+        assert_eq!(result.err(), Some(Malformed { offset: 4 }));
+
+        // trailing bytes
+        let result = MsgpackArray::<Vec<u8>, Int64>::parse(vec![0x90u8, 0]);
+        assert_eq!(result.err(), Some(Malformed { offset: 1 }));
+
+        // non-canonical header
+        let result = MsgpackArray::<Vec<u8>, Int64>::parse(vec![0xdcu8, 0, 0]);
+        assert_eq!(result.err(), Some(Malformed { offset: 1 }));
+    }
+
+    #[test]
+    fn test_corrupted_element() {
+        let elements = (0..10).map(Int64).collect::<Vec<_>>();
+        let mut arr: MsgpackArray<Vec<u8>, Int64> =
+            MsgpackArray::with_elements(|len| Ok(vec![0u8; len]), &elements).unwrap();
+
+        // break the type byte of the element at index 5
+        arr.underlying[1 + 5 * 9] = 0xcc;
+        assert_eq!(arr.get(4), Ok(Some(Int64(4))));
+        assert_eq!(arr.get(5), Err(Malformed { offset: 46 }));
+        assert_eq!(arr.get(10), Ok(None));
+
+        // search paths hitting the element fail instead of panic
+        assert_eq!(arr.binarysearch(Int64(5)), Err(Malformed { offset: 46 }));
+        assert_eq!(arr.lower_bound(Int64(7)), Err(Malformed { offset: 46 }));
+        assert_eq!(arr.upper_bound(Int64(3)), Err(Malformed { offset: 46 }));
+        assert_eq!(arr.quantile(0.3), Ok(Some(Int64(2))));
+        assert_eq!(arr.quantile(0.6), Err(Malformed { offset: 46 }));
+
+        // insert / delete don't read elements
+        assert_eq!(arr.insert_many(&[(0, Int64(-1))]), Ok(()));
+        assert_eq!(arr.get(6), Err(Malformed { offset: 55 }));
+        assert_eq!(arr.delete_at(6), Ok(()));
+        assert_eq!(arr.binarysearch(Int64(5)), Ok(NotFound(6)));
+    }
+
+    #[test]
+    fn test_parse_header() {
+        assert_eq!(ArrayHeader::parse(&vec![0x95u8]), Some(ArrayHeader::Fix(5)));
+        assert_eq!(
+            ArrayHeader::parse(&vec![0xdcu8, 0x01, 0x00]),
+            Some(ArrayHeader::Array16(256))
+        );
         assert_eq!(
-            MsgpackArray::<Vec<u8>, Int64>::parse(vec![0u8; 5]).is_none(),
-            true
+            ArrayHeader::parse(&vec![0xddu8, 0x00, 0x01, 0x00, 0x00]),
+            Some(ArrayHeader::Array32(65536))
         );
+        assert_eq!(ArrayHeader::parse(&vec![0xdcu8, 0x01]), None);
+        assert_eq!(ArrayHeader::parse(&vec![0xd3u8]), None);
+        assert_eq!(ArrayHeader::parse(&Vec::<u8>::new()), None);
+    }
+
+    #[test]
+    fn test_int32() {
+        let elements = [Int32(i32::MIN), Int32(-1), Int32(0), Int32(i32::MAX)];
+        let mut arr: MsgpackArray<Vec<u8>, Int32> =
+            MsgpackArray::with_elements(|len| Ok(vec![0u8; len]), &elements).unwrap();
+        assert_eq!(arr.underlying.len(), 4 * 5 + 1);
+        assert_eq!(&arr.underlying[1..6], &[0xd2, 0x80, 0, 0, 0]);
+        assert_eq!(arr.get(1), Ok(Some(Int32(-1))));
+        assert_eq!(arr.get(3), Ok(Some(Int32(i32::MAX))));
+
+        arr.insert_many(&[(3, Int32(42))]).unwrap();
+        assert_eq!(arr.binarysearch(Int32(42)), Ok(Found(3)));
+        assert_eq!(arr.binarysearch(Int32(i32::MAX)), Ok(Found(4)));
+
+        let bytes = arr.underlying;
+        assert!(MsgpackArray::<Vec<u8>, Int32>::parse(bytes.clone()).is_ok());
+        assert!(MsgpackArray::<Vec<u8>, Int64>::parse(bytes).is_err());
     }
 
     #[test]
+    #[allow(clippy::same_item_push)]
     fn test_index() {
         let mut v = vec![0x91u8];
         for _ in 0..1 {
@@ -360,9 +610,9 @@ mod tests {
         }
         let mut arr = MsgpackArray::parse(v).unwrap();
 
-        assert_eq!(arr.get(0), Some(Int64(0)));
+        assert_eq!(arr.get(0), Ok(Some(Int64(0))));
         arr.set(0, Int64(123456789));
-        assert_eq!(arr.get(0), Some(Int64(123456789)));
+        assert_eq!(arr.get(0), Ok(Some(Int64(123456789))));
 
         let mut v = vec![0xdc, 0, 100u8];
         for _ in 0..100 {
@@ -373,12 +623,13 @@ mod tests {
         }
         let mut arr = MsgpackArray::parse(v).unwrap();
 
-        assert_eq!(arr.get(99), Some(Int64(0)));
+        assert_eq!(arr.get(99), Ok(Some(Int64(0))));
         arr.set(99, Int64(123456789));
-        assert_eq!(arr.get(99), Some(Int64(123456789)));
+        assert_eq!(arr.get(99), Ok(Some(Int64(123456789))));
     }
 
     #[test]
+    #[allow(clippy::same_item_push)]
     fn test_binarysearch_found() {
         // found
         let mut v = vec![0x96];
@@ -395,7 +646,7 @@ mod tests {
         arr.set(3, Int64(7));
         arr.set(4, Int64(11));
         arr.set(5, Int64(13));
-        assert_eq!(arr.binarysearch(Int64(7)), Found(3));
+        assert_eq!(arr.binarysearch(Int64(7)), Ok(Found(3)));
 
         let mut v = vec![0x91];
         for _ in 0..1 {
@@ -406,7 +657,7 @@ mod tests {
         }
         let mut arr = MsgpackArray::parse(v).unwrap();
         arr.set(0, Int64(7));
-        assert_eq!(arr.binarysearch(Int64(7)), Found(0));
+        assert_eq!(arr.binarysearch(Int64(7)), Ok(Found(0)));
 
         let mut v = vec![0x93];
         for _ in 0..3 {
@@ -419,11 +670,12 @@ mod tests {
         arr.set(0, Int64(2));
         arr.set(1, Int64(3));
         arr.set(2, Int64(5));
-        assert_eq!(arr.binarysearch(Int64(2)), Found(0));
-        assert_eq!(arr.binarysearch(Int64(5)), Found(2));
+        assert_eq!(arr.binarysearch(Int64(2)), Ok(Found(0)));
+        assert_eq!(arr.binarysearch(Int64(5)), Ok(Found(2)));
     }
 
     #[test]
+    #[allow(clippy::same_item_push)]
     fn test_binarysearch_notfound() {
         // not found
         let mut v = vec![0x96];
@@ -440,7 +692,7 @@ mod tests {
         arr.set(3, Int64(7));
         arr.set(4, Int64(11));
         arr.set(5, Int64(13));
-        assert_eq!(arr.binarysearch(Int64(4)), NotFound(2));
+        assert_eq!(arr.binarysearch(Int64(4)), Ok(NotFound(2)));
 
         let mut v = vec![0x91];
         for _ in 0..1 {
@@ -451,43 +703,87 @@ mod tests {
         }
         let mut arr = MsgpackArray::parse(v).unwrap();
         arr.set(0, Int64(7));
-        assert_eq!(arr.binarysearch(Int64(3)), NotFound(0));
-        assert_eq!(arr.binarysearch(Int64(8)), NotFound(1));
+        assert_eq!(arr.binarysearch(Int64(3)), Ok(NotFound(0)));
+        assert_eq!(arr.binarysearch(Int64(8)), Ok(NotFound(1)));
 
         let arr = MsgpackArray::parse(vec![0x90]).unwrap();
-        assert_eq!(arr.binarysearch(Int64(8)), NotFound(0));
+        assert_eq!(arr.binarysearch(Int64(8)), Ok(NotFound(0)));
     }
 
     #[test]
     fn test_binarysearch_first() {
         let mut array: MsgpackArray<Vec<u8>, Int64> =
             MsgpackArray::new(|len| Ok(vec![0u8; len])).unwrap();
-        assert_eq!(array.binarysearch(Int64(3)), NotFound(0));
+        assert_eq!(array.binarysearch(Int64(3)), Ok(NotFound(0)));
 
-        array.insert_at(0, Int64(3));
-        assert_eq!(array.binarysearch(Int64(2)), NotFound(0));
+        array.insert_many(&[(0, Int64(3))]).unwrap();
+        assert_eq!(array.binarysearch(Int64(2)), Ok(NotFound(0)));
 
-        array.insert_at(0, Int64(2));
-        assert_eq!(array.binarysearch(Int64(1)), NotFound(0));
+        array.insert_many(&[(0, Int64(2))]).unwrap();
+        assert_eq!(array.binarysearch(Int64(1)), Ok(NotFound(0)));
 
-        array.insert_at(0, Int64(1));
-        assert_eq!(array.binarysearch(Int64(1)), Found(0));
+        array.insert_many(&[(0, Int64(1))]).unwrap();
+        assert_eq!(array.binarysearch(Int64(1)), Ok(Found(0)));
     }
 
     #[test]
     fn test_binarysearch_last() {
         let mut array: MsgpackArray<Vec<u8>, Int64> =
             MsgpackArray::new(|len| Ok(vec![0u8; len])).unwrap();
-        assert_eq!(array.binarysearch(Int64(1)), NotFound(0));
+        assert_eq!(array.binarysearch(Int64(1)), Ok(NotFound(0)));
+
+        array.insert_many(&[(0, Int64(1))]).unwrap();
+        assert_eq!(array.binarysearch(Int64(2)), Ok(NotFound(1)));
+
+        array.insert_many(&[(1, Int64(2))]).unwrap();
+        assert_eq!(array.binarysearch(Int64(3)), Ok(NotFound(2)));
+
+        array.insert_many(&[(2, Int64(3))]).unwrap();
+        assert_eq!(array.binarysearch(Int64(3)), Ok(Found(2)));
+    }
+
+    #[test]
+    fn test_bound() {
+        let mut array: MsgpackArray<Vec<u8>, Int64> =
+            MsgpackArray::new(|len| Ok(vec![0u8; len])).unwrap();
+        assert_eq!(array.lower_bound(Int64(3)), Ok(0));
+        assert_eq!(array.upper_bound(Int64(3)), Ok(0));
 
-        array.insert_at(0, Int64(1));
-        assert_eq!(array.binarysearch(Int64(2)), NotFound(1));
+        for (i, n) in [2, 3, 5, 7].iter().enumerate() {
+            array.insert_many(&[(i, Int64(*n))]).unwrap();
+        }
+        assert_eq!(array.lower_bound(Int64(1)), Ok(0));
+        assert_eq!(array.upper_bound(Int64(1)), Ok(0));
+        assert_eq!(array.lower_bound(Int64(3)), Ok(1));
+        assert_eq!(array.upper_bound(Int64(3)), Ok(2));
+        assert_eq!(array.lower_bound(Int64(4)), Ok(2));
+        assert_eq!(array.upper_bound(Int64(4)), Ok(2));
+        assert_eq!(array.lower_bound(Int64(7)), Ok(3));
+        assert_eq!(array.upper_bound(Int64(7)), Ok(4));
+        assert_eq!(array.lower_bound(Int64(8)), Ok(4));
+        assert_eq!(array.upper_bound(Int64(8)), Ok(4));
+    }
 
-        array.insert_at(1, Int64(2));
-        assert_eq!(array.binarysearch(Int64(3)), NotFound(2));
+    #[test]
+    fn test_quantile() {
+        let mut array: MsgpackArray<Vec<u8>, Int64> =
+            MsgpackArray::new(|len| Ok(vec![0u8; len])).unwrap();
+        assert_eq!(array.quantile(0.5), Ok(None));
 
-        array.insert_at(2, Int64(3));
-        assert_eq!(array.binarysearch(Int64(3)), Found(2));
+        for i in 0..10 {
+            array
+                .insert_many(&[(i, Int64((i as i64 + 1) * 10))])
+                .unwrap();
+        }
+        assert_eq!(array.quantile(0.0), Ok(Some(Int64(10))));
+        assert_eq!(array.quantile(0.1), Ok(Some(Int64(10))));
+        assert_eq!(array.quantile(0.25), Ok(Some(Int64(30))));
+        assert_eq!(array.quantile(0.5), Ok(Some(Int64(50))));
+        assert_eq!(array.quantile(0.99), Ok(Some(Int64(100))));
+        assert_eq!(array.quantile(1.0), Ok(Some(Int64(100))));
+        assert_eq!(array.quantile(1.5), Ok(None));
+        assert_eq!(array.quantile(-0.1), Ok(None));
+        assert_eq!(array.quantile(f64::NAN), Ok(None));
     }
 
     #[test]
@@ -495,35 +791,70 @@ mod tests {
     fn test_insert_at() {
         let mut array: MsgpackArray<Vec<u8>, Int64> =
             MsgpackArray::new(|len| Ok(vec![0u8; len])).unwrap();
-        array.insert_at(0, Int64(2));
+        array.insert_many(&[(0, Int64(2))]).unwrap();
         assert_eq!(array.header(), ArrayHeader::Fix(1));
 
         for (i, n) in [3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47]
             .iter()
             .enumerate()
         {
-            array.insert_at(i + 1, Int64(*n));
+            array.insert_many(&[(i + 1, Int64(*n))]).unwrap();
         }
         assert_eq!(array.header(), ArrayHeader::Fix(15));
-        assert_eq!(array.get(14), Some(Int64(47)));
+        assert_eq!(array.get(14), Ok(Some(Int64(47))));
 
-        array.insert_at(15, Int64(53));
+        array.insert_many(&[(15, Int64(53))]).unwrap();
         assert_eq!(array.header(), ArrayHeader::Array16(16));
-        assert_eq!(array.get(0), Some(Int64(2)));
-        assert_eq!(array.get(5), Some(Int64(13)));
-        assert_eq!(array.get(15), Some(Int64(53)));
+        assert_eq!(array.get(0), Ok(Some(Int64(2))));
+        assert_eq!(array.get(5), Ok(Some(Int64(13))));
+        assert_eq!(array.get(15), Ok(Some(Int64(53))));
 
         for i in 0..65519 {
-            array.insert_at(16 + i, Int64(i as i64));
+            array.insert_many(&[(16 + i, Int64(i as i64))]).unwrap();
         }
         assert_eq!(array.header(), ArrayHeader::Array16(65535));
-        assert_eq!(array.get(65534), Some(Int64(65518)));
+        assert_eq!(array.get(65534), Ok(Some(Int64(65518))));
 
-        array.insert_at(32768, Int64(-42));
+        array.insert_many(&[(32768, Int64(-42))]).unwrap();
         assert_eq!(array.header(), ArrayHeader::Array32(65536));
-        assert_eq!(array.get(0), Some(Int64(2)));
-        assert_eq!(array.get(32768), Some(Int64(-42)));
-        assert_eq!(array.get(65535), Some(Int64(65518)));
+        assert_eq!(array.get(0), Ok(Some(Int64(2))));
+        assert_eq!(array.get(32768), Ok(Some(Int64(-42))));
+        assert_eq!(array.get(65535), Ok(Some(Int64(65518))));
+    }
+
+    #[test]
+    fn test_insert_many() {
+        let mut arr: MsgpackArray<Vec<u8>, Int64> =
+            MsgpackArray::with_elements(|len| Ok(vec![0; len]), &[Int64(2), Int64(4)]).unwrap();
+
+        arr.insert_many(&[(0, Int64(1)), (1, Int64(3)), (2, Int64(5)), (2, Int64(6))])
+            .unwrap();
+        assert_eq!(arr.header(), ArrayHeader::Fix(6));
+        assert_eq!(arr.underlying.len(), 1 + 6 * 9);
+        for i in 0..6 {
+            assert_eq!(arr.get(i), Ok(Some(Int64(i as i64 + 1))));
+        }
+
+        // header grows from fixarray to array16
+        let elements = (0..15).map(|n| Int64(n * 2)).collect::<Vec<_>>();
+        let mut arr: MsgpackArray<Vec<u8>, Int64> =
+            MsgpackArray::with_elements(|len| Ok(vec![0; len]), &elements).unwrap();
+        arr.insert_many(&[(0, Int64(-1)), (15, Int64(100))])
+            .unwrap();
+        assert_eq!(arr.header(), ArrayHeader::Array16(17));
+        assert_eq!(arr.underlying.len(), 3 + 17 * 9);
+        assert_eq!(arr.get(0), Ok(Some(Int64(-1))));
+        for i in 0..15 {
+            assert_eq!(arr.get(i + 1), Ok(Some(Int64(i as i64 * 2))));
+        }
+        assert_eq!(arr.get(16), Ok(Some(Int64(100))));
+
+        // into empty array
+        let mut arr: MsgpackArray<Vec<u8>, Int64> =
+            MsgpackArray::new(|len| Ok(vec![0; len])).unwrap();
+        arr.insert_many(&[(0, Int64(7)), (0, Int64(8))]).unwrap();
+        assert_eq!(arr.get(0), Ok(Some(Int64(7))));
+        assert_eq!(arr.get(1), Ok(Some(Int64(8))));
     }
 
     #[test]
@@ -536,27 +867,63 @@ mod tests {
         .iter()
         .enumerate()
         {
-            array.insert_at(i, Int64(*n));
+            array.insert_many(&[(i, Int64(*n))]).unwrap();
         }
         assert_eq!(array.header(), ArrayHeader::Array16(17));
 
         array.delete_at(0);
         assert_eq!(array.header(), ArrayHeader::Array16(16));
-        assert_eq!(array.get(0), Some(Int64(3)));
-        assert_eq!(array.get(15), Some(Int64(59)));
+        assert_eq!(array.get(0), Ok(Some(Int64(3))));
+        assert_eq!(array.get(15), Ok(Some(Int64(59))));
 
         array.delete_at(15);
         assert_eq!(array.header(), ArrayHeader::Fix(15));
-        assert_eq!(array.get(0), Some(Int64(3)));
-        assert_eq!(array.get(14), Some(Int64(53)));
+        assert_eq!(array.get(0), Ok(Some(Int64(3))));
+        assert_eq!(array.get(14), Ok(Some(Int64(53))));
 
         array.delete_at(5);
         assert_eq!(array.header(), ArrayHeader::Fix(14));
-        assert_eq!(array.get(0), Some(Int64(3)));
-        assert_eq!(array.get(13), Some(Int64(53)));
-        assert_eq!(array.get(5), Some(Int64(19)));
+        assert_eq!(array.get(0), Ok(Some(Int64(3))));
+        assert_eq!(array.get(13), Ok(Some(Int64(53))));
+        assert_eq!(array.get(5), Ok(Some(Int64(19))));
 
         array.delete_at(99);
         assert_eq!(array.header(), ArrayHeader::Fix(14));
     }
+
+    #[test]
+    fn test_delete_range() {
+        let mut array: MsgpackArray<Vec<u8>, Int64> =
+            MsgpackArray::new(|len| Ok(vec![0u8; len])).unwrap();
+        for i in 0..20 {
+            array.insert_many(&[(i, Int64(i as i64))]).unwrap();
+        }
+        assert_eq!(array.header(), ArrayHeader::Array16(20));
+
+        assert_eq!(array.delete_range(5, 5), Ok(0));
+        assert_eq!(array.delete_range(25, 30), Ok(0));
+        assert_eq!(array.header(), ArrayHeader::Array16(20));
+
+        assert_eq!(array.delete_range(2, 4), Ok(2));
+        assert_eq!(array.header(), ArrayHeader::Array16(18));
+        assert_eq!(array.get(1), Ok(Some(Int64(1))));
+        assert_eq!(array.get(2), Ok(Some(Int64(4))));
+        assert_eq!(array.get(17), Ok(Some(Int64(19))));
+
+        assert_eq!(array.delete_range(5, 9), Ok(4));
+        assert_eq!(array.header(), ArrayHeader::Fix(14));
+        assert_eq!(array.underlying.len(), 14 * 9 + 1);
+        assert_eq!(array.get(0), Ok(Some(Int64(0))));
+        assert_eq!(array.get(4), Ok(Some(Int64(6))));
+        assert_eq!(array.get(5), Ok(Some(Int64(11))));
+        assert_eq!(array.get(13), Ok(Some(Int64(19))));
+
+        assert_eq!(array.delete_range(10, 99), Ok(4));
+        assert_eq!(array.header(), ArrayHeader::Fix(10));
+        assert_eq!(array.get(9), Ok(Some(Int64(15))));
+
+        assert_eq!(array.delete_range(0, 10), Ok(10));
+        assert_eq!(array.header(), ArrayHeader::Fix(0));
+        assert_eq!(array.underlying, vec![0x90]);
+    }
 }
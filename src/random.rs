@@ -0,0 +1,88 @@
+//! Pseudo random sampling of array indices.
+
+use std::collections::HashMap;
+
+/// xorshift64* generator. Not cryptographically secure.
+pub struct Random {
+    state: u64,
+}
+
+impl Random {
+    pub fn new(seed: u64) -> Self {
+        // xorshift gets stuck at zero state
+        Random {
+            state: if seed == 0 {
+                0x9e37_79b9_7f4a_7c15
+            } else {
+                seed
+            },
+        }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    /// Returns uniformly distributed integer in [0, n)
+    pub fn below(&mut self, n: usize) -> usize {
+        ((self.next_u64() as u128 * n as u128) >> 64) as usize
+    }
+
+    /// Pick `count` distinct indices from [0, len) in random order.
+    /// All indices are returned (shuffled) if `count` exceeds `len`.
+    pub fn distinct_indices(&mut self, len: usize, count: usize) -> Vec<usize> {
+        let count = count.min(len);
+
+        // partial Fisher-Yates shuffle over virtual [0, len) array.
+        // only swapped positions are remembered.
+        let mut swapped: HashMap<usize, usize> = HashMap::new();
+        let mut ret = Vec::with_capacity(count);
+        for i in 0..count {
+            let j = i + self.below(len - i);
+            let vi = *swapped.get(&i).unwrap_or(&i);
+            let vj = *swapped.get(&j).unwrap_or(&j);
+            swapped.insert(j, vi);
+            ret.push(vj);
+        }
+        ret
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Random;
+
+    #[test]
+    fn test_below() {
+        let mut random = Random::new(42);
+        for n in 1..100 {
+            assert!(random.below(n) < n);
+        }
+        assert_eq!(random.below(1), 0);
+
+        let mut zero = Random::new(0);
+        assert_ne!(zero.next_u64(), 0);
+    }
+
+    #[test]
+    fn test_distinct_indices() {
+        let mut random = Random::new(42);
+
+        let mut indices = random.distinct_indices(10, 5);
+        assert_eq!(indices.len(), 5);
+        indices.sort();
+        indices.dedup();
+        assert_eq!(indices.len(), 5);
+        assert!(indices.iter().all(|&i| i < 10));
+
+        let mut indices = random.distinct_indices(10, 20);
+        indices.sort();
+        assert_eq!(indices, (0..10).collect::<Vec<_>>());
+
+        assert_eq!(random.distinct_indices(0, 3), vec![]);
+        assert_eq!(random.distinct_indices(3, 0), vec![]);
+    }
+}
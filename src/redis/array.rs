@@ -0,0 +1,336 @@
+//! Msgpack array held by a key, of any supported element type.
+//!
+//! Element type is determined by the first element. Empty array is regarded as the default type.
+
+use super::buffer::KeyBuffer;
+use super::REDISMODULE_ERR;
+use crate::msgpack::format::{Int32, Int64, Primitive};
+use crate::msgpack::{ArrayHeader, ByteSlice, Malformed, MsgpackArray, SearchResult};
+use libc::c_int;
+use std::convert::TryFrom;
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum ElementType {
+    I64,
+    I32,
+}
+
+impl ElementType {
+    pub fn parse(name: &[u8]) -> Option<Self> {
+        if name.eq_ignore_ascii_case(b"i64") {
+            Some(ElementType::I64)
+        } else if name.eq_ignore_ascii_case(b"i32") {
+            Some(ElementType::I32)
+        } else {
+            None
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ElementType::I64 => "i64",
+            ElementType::I32 => "i32",
+        }
+    }
+
+    /// Returns true if the value is representable by the element type
+    pub fn fits(self, value: i64) -> bool {
+        match self {
+            ElementType::I64 => true,
+            ElementType::I32 => i32::try_from(value).is_ok(),
+        }
+    }
+
+    /// Returns the size in bytes of an array of `len` elements
+    pub fn total_bytes(self, len: usize) -> usize {
+        let header = ArrayHeader::from_len(len);
+        match self {
+            ElementType::I64 => header.total_bytes::<Int64>(),
+            ElementType::I32 => header.total_bytes::<Int32>(),
+        }
+    }
+
+    /// Encode elements into msgpack bytes.
+    /// Elements must be sorted in ascending order without duplicates and must fit the type.
+    pub fn encode(self, elements: &[i64]) -> Vec<u8> {
+        let allocator = |len| Ok::<_, ()>(vec![0; len]);
+        let bytes = match self {
+            ElementType::I64 => {
+                let elements = elements.iter().map(|&n| Int64(n)).collect::<Vec<_>>();
+                MsgpackArray::with_elements(allocator, &elements).map(MsgpackArray::into_inner)
+            }
+            ElementType::I32 => {
+                let elements = elements
+                    .iter()
+                    .map(|&n| Int32(n as i32))
+                    .collect::<Vec<_>>();
+                MsgpackArray::with_elements(allocator, &elements).map(MsgpackArray::into_inner)
+            }
+        };
+        bytes.unwrap_or_default()
+    }
+
+    /// Detect element type of the bytes, checking the header and the total length.
+    /// Returns `default` for an empty array.
+    pub fn of<T: ByteSlice>(bytes: &T, default: ElementType) -> Option<Self> {
+        let header = ArrayHeader::parse(bytes)?;
+        if header.len() == 0 {
+            return if bytes.len() == header.header_bytes() {
+                Some(default)
+            } else {
+                None
+            };
+        }
+        if bytes.len() <= header.header_bytes() {
+            return None;
+        }
+
+        let (element_type, total_bytes) = match bytes[header.header_bytes()] {
+            Int64::FIRST_BYTE => (ElementType::I64, header.total_bytes::<Int64>()),
+            Int32::FIRST_BYTE => (ElementType::I32, header.total_bytes::<Int32>()),
+            _ => return None,
+        };
+        if bytes.len() == total_bytes {
+            Some(element_type)
+        } else {
+            None
+        }
+    }
+}
+
+/// Array over `KeyBuffer` can be modified, while array over `ReadOnlyBuffer` can only be read.
+pub enum Array<B: ByteSlice = KeyBuffer> {
+    I64(MsgpackArray<B, Int64>),
+    I32(MsgpackArray<B, Int32>),
+}
+
+macro_rules! dispatch {
+    ($array:expr, $inner:ident => $e:expr) => {
+        match $array {
+            Array::I64($inner) => $e,
+            Array::I32($inner) => $e,
+        }
+    };
+}
+
+impl<B: ByteSlice> Array<B> {
+    pub fn parse(buffer: B, default: ElementType) -> Option<Self> {
+        match ElementType::of(&buffer, default)? {
+            ElementType::I64 => MsgpackArray::parse(buffer).map(Array::I64).ok(),
+            ElementType::I32 => MsgpackArray::parse(buffer).map(Array::I32).ok(),
+        }
+    }
+
+    pub fn element_type(&self) -> ElementType {
+        match self {
+            Array::I64(_) => ElementType::I64,
+            Array::I32(_) => ElementType::I32,
+        }
+    }
+
+    pub fn header(&self) -> ArrayHeader {
+        dispatch!(self, array => array.header())
+    }
+
+    pub fn len(&self) -> usize {
+        self.header().len()
+    }
+
+    pub fn get(&self, index: usize) -> Result<Option<i64>, Malformed> {
+        match self {
+            Array::I64(array) => Ok(array.get(index)?.map(|Int64(n)| n)),
+            Array::I32(array) => Ok(array.get(index)?.map(|Int32(n)| n as i64)),
+        }
+    }
+
+    /// Read all elements in ascending order
+    pub fn to_vec(&self) -> Result<Vec<i64>, Malformed> {
+        (0..self.len())
+            .map(|index| self.get(index).map(Option::unwrap_or_default))
+            .collect()
+    }
+
+    pub fn quantile(&self, q: f64) -> Result<Option<i64>, Malformed> {
+        match self {
+            Array::I64(array) => Ok(array.quantile(q)?.map(|Int64(n)| n)),
+            Array::I32(array) => Ok(array.quantile(q)?.map(|Int32(n)| n as i64)),
+        }
+    }
+
+    pub fn binarysearch(&self, element: i64) -> Result<SearchResult, Malformed> {
+        match self {
+            Array::I64(array) => array.binarysearch(Int64(element)),
+            Array::I32(array) => match i32::try_from(element) {
+                Ok(n) => array.binarysearch(Int32(n)),
+                // out of range values are never found, and sorted to either end
+                Err(_) if element < 0 => Ok(SearchResult::NotFound(0)),
+                Err(_) => Ok(SearchResult::NotFound(array.header().len())),
+            },
+        }
+    }
+
+    pub fn contains(&self, element: i64) -> Result<bool, Malformed> {
+        match self.binarysearch(element)? {
+            SearchResult::Found(_) => Ok(true),
+            SearchResult::NotFound(_) => Ok(false),
+        }
+    }
+
+    /// Returns the index of the first element which is not less than given element
+    pub fn lower_bound(&self, element: i64) -> Result<usize, Malformed> {
+        match self {
+            Array::I64(array) => array.lower_bound(Int64(element)),
+            Array::I32(array) => match i32::try_from(element) {
+                Ok(n) => array.lower_bound(Int32(n)),
+                Err(_) if element < 0 => Ok(0),
+                Err(_) => Ok(array.header().len()),
+            },
+        }
+    }
+
+    /// Returns the index of the first element which is greater than given element
+    pub fn upper_bound(&self, element: i64) -> Result<usize, Malformed> {
+        match self {
+            Array::I64(array) => array.upper_bound(Int64(element)),
+            Array::I32(array) => match i32::try_from(element) {
+                Ok(n) => array.upper_bound(Int32(n)),
+                Err(_) if element < 0 => Ok(0),
+                Err(_) => Ok(array.header().len()),
+            },
+        }
+    }
+
+    pub fn into_inner(self) -> B {
+        dispatch!(self, array => array.into_inner())
+    }
+}
+
+impl Array {
+    pub fn new<F>(element_type: ElementType, allocator: F) -> Result<Self, c_int>
+    where
+        F: FnOnce(usize) -> Result<KeyBuffer, c_int>,
+    {
+        match element_type {
+            ElementType::I64 => MsgpackArray::new(allocator).map(Array::I64),
+            ElementType::I32 => MsgpackArray::new(allocator).map(Array::I32),
+        }
+    }
+
+    /// Initialize array with given elements at once.
+    /// Elements must be sorted in ascending order without duplicates and must fit the type.
+    pub fn with_elements<F>(
+        element_type: ElementType,
+        allocator: F,
+        elements: &[i64],
+    ) -> Result<Self, c_int>
+    where
+        F: FnOnce(usize) -> Result<KeyBuffer, c_int>,
+    {
+        match element_type {
+            ElementType::I64 => {
+                let elements = elements.iter().map(|&n| Int64(n)).collect::<Vec<_>>();
+                MsgpackArray::with_elements(allocator, &elements).map(Array::I64)
+            }
+            ElementType::I32 => {
+                let elements = elements
+                    .iter()
+                    .map(|&n| i32::try_from(n).map(Int32))
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(|_| REDISMODULE_ERR)?;
+                MsgpackArray::with_elements(allocator, &elements).map(Array::I32)
+            }
+        }
+    }
+
+    /// Insert elements at once. See `MsgpackArray::insert_many`.
+    pub fn insert_many(&mut self, elements: &[(usize, i64)]) -> Result<(), c_int> {
+        match self {
+            Array::I64(array) => {
+                let elements = elements
+                    .iter()
+                    .map(|&(index, n)| (index, Int64(n)))
+                    .collect::<Vec<_>>();
+                array.insert_many(&elements)
+            }
+            Array::I32(array) => {
+                let elements = elements
+                    .iter()
+                    .map(|&(index, n)| i32::try_from(n).map(|n| (index, Int32(n))))
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(|_| REDISMODULE_ERR)?;
+                array.insert_many(&elements)
+            }
+        }
+    }
+
+    pub fn delete_at(&mut self, index: usize) -> Result<(), c_int> {
+        dispatch!(self, array => array.delete_at(index))
+    }
+
+    pub fn delete_range(&mut self, start: usize, end: usize) -> Result<usize, c_int> {
+        dispatch!(self, array => array.delete_range(start, end))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ElementType;
+
+    #[test]
+    fn test_element_type() {
+        assert_eq!(ElementType::parse(b"I32"), Some(ElementType::I32));
+        assert_eq!(ElementType::parse(b"i64"), Some(ElementType::I64));
+        assert_eq!(ElementType::parse(b"u8"), None);
+
+        assert!(ElementType::I32.fits(i32::MIN as i64));
+        assert!(!ElementType::I32.fits(i32::MAX as i64 + 1));
+        assert!(ElementType::I64.fits(i64::MIN));
+
+        assert_eq!(ElementType::I64.total_bytes(0), 1);
+        assert_eq!(ElementType::I64.total_bytes(2), 1 + 2 * 9);
+        assert_eq!(ElementType::I32.total_bytes(16), 3 + 16 * 5);
+
+        assert_eq!(
+            ElementType::I32.encode(&[-1, 1]),
+            vec![0x92, 0xd2, 0xff, 0xff, 0xff, 0xff, 0xd2, 0, 0, 0, 1]
+        );
+        assert_eq!(ElementType::I64.encode(&[]), vec![0x90]);
+    }
+
+    #[test]
+    fn test_element_type_of() {
+        let empty = vec![0x90u8];
+        assert_eq!(
+            ElementType::of(&empty, ElementType::I32),
+            Some(ElementType::I32)
+        );
+        assert_eq!(
+            ElementType::of(&empty, ElementType::I64),
+            Some(ElementType::I64)
+        );
+
+        let i64s = vec![0x91u8, 0xd3, 0, 0, 0, 0, 0, 0, 0, 1];
+        assert_eq!(
+            ElementType::of(&i64s, ElementType::I32),
+            Some(ElementType::I64)
+        );
+
+        let i32s = vec![0x92u8, 0xd2, 0, 0, 0, 1, 0xd2, 0, 0, 0, 2];
+        assert_eq!(
+            ElementType::of(&i32s, ElementType::I64),
+            Some(ElementType::I32)
+        );
+
+        // truncated, trailing garbage or unknown element type
+        assert_eq!(
+            ElementType::of(&i32s[..10].to_vec(), ElementType::I64),
+            None
+        );
+        assert_eq!(ElementType::of(&vec![0x90u8, 0], ElementType::I64), None);
+        assert_eq!(ElementType::of(&vec![0x91u8], ElementType::I64), None);
+        assert_eq!(
+            ElementType::of(&vec![0x91u8, 0xcc, 1], ElementType::I64),
+            None
+        );
+    }
+}
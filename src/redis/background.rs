@@ -0,0 +1,119 @@
+//! Computation of heavy read-only commands off the main thread.
+//!
+//! Inputs are snapshotted as raw bytes on the main thread, so the computation doesn't touch the keyspace.
+//! The client is blocked until the result is replied via a thread-safe context.
+//! Computations are queued to a single worker thread and run in order.
+
+use super::config::config;
+use super::*;
+use crate::msgpack::Malformed;
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr::null_mut;
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Mutex, PoisonError};
+use std::thread;
+
+type Job = Box<dyn FnOnce() + Send>;
+
+/// Jobs run one at a time on a single worker thread, so that heavy commands don't pile up threads
+static WORKER: Mutex<Option<Sender<Job>>> = Mutex::new(None);
+
+struct BlockedClient(*mut RedisModuleBlockedClient);
+
+// blocked client handle is meant to be passed to another thread
+unsafe impl Send for BlockedClient {}
+
+/// Returns true if the command over `total_elements` should run on the worker thread.
+/// Clients in MULTI, scripts or other contexts that can't block are always served inline.
+pub fn should_block(ctx: *mut RedisModuleCtx, total_elements: usize) -> bool {
+    let threshold = config().background_threshold;
+    if threshold == 0 || total_elements < threshold {
+        return false;
+    }
+
+    unsafe {
+        match RedisModule_GetContextFlags {
+            Some(get_context_flags) => {
+                get_context_flags(ctx)
+                    & (REDISMODULE_CTX_FLAGS_LUA
+                        | REDISMODULE_CTX_FLAGS_MULTI
+                        | REDISMODULE_CTX_FLAGS_DENY_BLOCKING)
+                    == 0
+            }
+            None => false,
+        }
+    }
+}
+
+/// Block the client and reply with elements computed on the worker thread
+pub fn reply_with_elements<F>(ctx: *mut RedisModuleCtx, compute: F) -> c_int
+where
+    F: FnOnce() -> Result<Vec<i64>, Malformed> + Send + 'static,
+{
+    let blocked = unsafe { RedisModule_BlockClient(ctx, None, None, None, 0) };
+    let bc = BlockedClient(blocked);
+
+    let submitted = submit(Box::new(move || {
+        let bc = bc;
+        let result = panic::catch_unwind(AssertUnwindSafe(compute));
+        unsafe {
+            let ctx = RedisModule_GetThreadSafeContext(bc.0);
+            match result {
+                Ok(Err(err)) => {
+                    reply_malformed(ctx, err);
+                }
+                Ok(Ok(elements)) => {
+                    RedisModule_ReplyWithArray(ctx, elements.len() as c_long);
+                    for n in elements {
+                        RedisModule_ReplyWithLongLong(ctx, n);
+                    }
+                }
+                Err(_) => {
+                    RedisModule_ReplyWithError(
+                        ctx,
+                        "ERR internal error in msgpack module\0".as_ptr(),
+                    );
+                }
+            }
+            RedisModule_FreeThreadSafeContext(ctx);
+            RedisModule_UnblockClient(bc.0, null_mut());
+        }
+    }));
+
+    if !submitted {
+        return unsafe {
+            RedisModule_AbortBlock(blocked);
+            RedisModule_ReplyWithError(ctx, "ERR failed to start background thread\0".as_ptr())
+        };
+    }
+    REDISMODULE_OK
+}
+
+/// Queue the job to the worker thread, starting it on first use.
+/// Returns false if the worker can't be started.
+fn submit(job: Job) -> bool {
+    let mut worker = WORKER.lock().unwrap_or_else(PoisonError::into_inner);
+    if worker.is_none() {
+        let (sender, receiver) = mpsc::channel::<Job>();
+        let spawned = thread::Builder::new()
+            .name("msgpack-bg".to_string())
+            .spawn(move || {
+                for job in receiver {
+                    job();
+                }
+            });
+        if spawned.is_err() {
+            return false;
+        }
+        *worker = Some(sender);
+    }
+
+    match worker.as_ref().map(|sender| sender.send(job)) {
+        Some(Ok(())) => true,
+        _ => {
+            // the worker has gone, start a new one next time
+            *worker = None;
+            false
+        }
+    }
+}
@@ -0,0 +1,120 @@
+use super::datatype::NativeBuffer;
+use super::dma::{ReadOnlyDMA, RedisDMA};
+use super::stats;
+use crate::msgpack::{ByteSlice, ByteVector};
+use libc::c_int;
+use std::ops::{Index, IndexMut};
+
+/// Bytes of msgpack array held by a key, either as string or as native value
+pub enum KeyBuffer {
+    String(RedisDMA),
+    Native(NativeBuffer),
+}
+
+impl ByteSlice for KeyBuffer {
+    fn len(&self) -> usize {
+        match self {
+            KeyBuffer::String(dma) => dma.len(),
+            KeyBuffer::Native(native) => native.len(),
+        }
+    }
+}
+
+impl ByteVector for KeyBuffer {
+    type AllocErr = c_int;
+
+    fn memmove(&mut self, dest: usize, src: usize, len: usize) {
+        stats::record_memmove(len);
+        match self {
+            KeyBuffer::String(dma) => dma.memmove(dest, src, len),
+            KeyBuffer::Native(native) => native.memmove(dest, src, len),
+        }
+    }
+
+    fn realloc(&self, len: usize) -> Result<Self, Self::AllocErr> {
+        stats::record_realloc();
+        match self {
+            KeyBuffer::String(dma) => dma.realloc(len).map(KeyBuffer::String),
+            KeyBuffer::Native(native) => native.realloc(len).map(KeyBuffer::Native),
+        }
+    }
+}
+
+impl KeyBuffer {
+    pub fn as_slice(&self) -> &[u8] {
+        match self {
+            KeyBuffer::String(dma) => dma.as_slice(),
+            KeyBuffer::Native(native) => native.as_slice(),
+        }
+    }
+
+    pub fn is_native(&self) -> bool {
+        match self {
+            KeyBuffer::String(_) => false,
+            KeyBuffer::Native(_) => true,
+        }
+    }
+}
+
+impl Index<usize> for KeyBuffer {
+    type Output = u8;
+
+    fn index(&self, index: usize) -> &Self::Output {
+        match self {
+            KeyBuffer::String(dma) => &dma[index],
+            KeyBuffer::Native(native) => &native[index],
+        }
+    }
+}
+
+impl IndexMut<usize> for KeyBuffer {
+    fn index_mut(&mut self, index: usize) -> &mut Self::Output {
+        match self {
+            KeyBuffer::String(dma) => &mut dma[index],
+            KeyBuffer::Native(native) => &mut native[index],
+        }
+    }
+}
+
+/// Bytes of msgpack array held by a key opened for read only.
+/// Implements only `ByteSlice`, so arrays over it can't be modified.
+pub enum ReadOnlyBuffer {
+    String(ReadOnlyDMA),
+    Native(NativeBuffer),
+}
+
+impl ByteSlice for ReadOnlyBuffer {
+    fn len(&self) -> usize {
+        match self {
+            ReadOnlyBuffer::String(dma) => dma.len(),
+            ReadOnlyBuffer::Native(native) => native.len(),
+        }
+    }
+}
+
+impl ReadOnlyBuffer {
+    pub fn as_slice(&self) -> &[u8] {
+        match self {
+            ReadOnlyBuffer::String(dma) => dma.as_slice(),
+            ReadOnlyBuffer::Native(native) => native.as_slice(),
+        }
+    }
+
+    pub fn is_native(&self) -> bool {
+        match self {
+            ReadOnlyBuffer::String(_) => false,
+            ReadOnlyBuffer::Native(_) => true,
+        }
+    }
+}
+
+impl Index<usize> for ReadOnlyBuffer {
+    type Output = u8;
+
+    fn index(&self, index: usize) -> &Self::Output {
+        match self {
+            ReadOnlyBuffer::String(dma) => &dma[index],
+            ReadOnlyBuffer::Native(native) => &native[index],
+        }
+    }
+}
@@ -1,15 +1,38 @@
 //! Redis commands implementation.
 
 use super::*;
-use crate::msgpack::format::Int64;
-use crate::msgpack::MsgpackArray;
-use crate::msgpack::SearchResult;
-use dma::RedisDMA;
-use libc::{c_int, size_t};
+use crate::msgpack::decode::{self, Tagged, Value};
+use crate::msgpack::{ArrayHeader, ByteSlice, ByteVector, Malformed, SearchResult};
+use crate::random::Random;
+use array::{Array, ElementType};
+use buffer::{KeyBuffer, ReadOnlyBuffer};
+use condition::Condition;
+use config::{command_name, config};
+use dma::{ReadOnlyDMA, RedisDMA};
+use libc::{c_double, c_int, c_long, c_longlong, size_t};
+use setop::SetOp;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+use validate::validate;
+
+static RANDOM_SEED: AtomicU64 = AtomicU64::new(0);
+
+const SADD_BATCH_SIZE: usize = 1024;
+
+/// Growth in bytes from which `exceeds_maxmemory` checks used memory exactly
+const MAXMEMORY_EXACT_GROWTH: usize = 1 << 20;
+
+/// Largest magnitude of integers exactly representable by double
+const MAX_EXACT_SCORE: i64 = 1 << 53;
 
 /// Upsert int64 to array32
 ///
-/// `redis-cli> MSGPACK.UPSERTI64 key [element ...]`
+/// TTL of the key is preserved unless expiration is specified, so KEEPTTL is accepted only for
+/// compatibility with SET. Options must precede elements.
+/// Returns nil without modification if a condition is not satisfied.
+///
+/// `redis-cli> MSGPACK.UPSERTI64 key [EX seconds|PX milliseconds|EXAT timestamp|PXAT milliseconds-timestamp|KEEPTTL] [NX|XX] [IFCARD n] [IFHASH h] [element ...]`
 #[allow(non_snake_case)]
 #[no_mangle]
 pub extern "C" fn UpsertI64_RedisCommand(
@@ -17,70 +40,220 @@ pub extern "C" fn UpsertI64_RedisCommand(
     argv: *mut *mut RedisModuleString,
     argc: c_int,
 ) -> c_int {
-    unsafe {
+    catch_panic(ctx, || unsafe {
         RedisModule_AutoMemory(ctx);
+        stats::record_command();
 
         if argc < 2 {
             return RedisModule_WrongArity(ctx);
         }
 
-        let Key(key, key_type) = open_rw(ctx, *argv.add(1));
+        let mut expire_at = None;
+        let mut keep_ttl = false;
+        let mut condition = Condition::default();
+        let mut elements = vec![];
+        let mut i = 2;
+        while i < argc as usize {
+            if let Some(n) = parse_i64(*argv.add(i)) {
+                elements.push(n);
+                i += 1;
+                continue;
+            }
+            if !elements.is_empty() {
+                return reply_not_integer(ctx);
+            }
 
-        if key_type != REDISMODULE_KEYTYPE_EMPTY && key_type != REDISMODULE_KEYTYPE_STRING {
-            return reply_wrong_type(ctx);
-        }
+            let value = (i + 1 < argc as usize).then(|| string_bytes(*argv.add(i + 1)));
+            match condition.parse(string_bytes(*argv.add(i)), value) {
+                Err(_) => return reply_syntax_error(ctx),
+                Ok(0) => {}
+                Ok(consumed) => {
+                    i += consumed;
+                    continue;
+                }
+            }
 
-        let mut array: MsgpackArray<RedisDMA, Int64>;
-        if key_type == REDISMODULE_KEYTYPE_EMPTY {
-            array = match MsgpackArray::new(|len| {
-                let ret = RedisModule_StringTruncate(key, len);
-                if ret != REDISMODULE_OK {
-                    Err(ret)
-                } else {
-                    Ok(string_dma(key))
+            let option = string_bytes(*argv.add(i)).to_ascii_uppercase();
+            if keep_ttl || expire_at.is_some() {
+                return reply_syntax_error(ctx);
+            }
+            if option == b"KEEPTTL" {
+                // no-op other than excluding expiration options
+                keep_ttl = true;
+                i += 1;
+                continue;
+            }
+            if i + 1 >= argc as usize {
+                return reply_syntax_error(ctx);
+            }
+            let value = match parse_i64(*argv.add(i + 1)) {
+                Some(value) => value,
+                None => return reply_not_integer(ctx),
+            };
+            expire_at = match absolute_expire(&option, value, RedisModule_Milliseconds()) {
+                Err(_) => return reply_syntax_error(ctx),
+                Ok(None) => {
+                    return RedisModule_ReplyWithError(
+                        ctx,
+                        "ERR invalid expire time in 'msgpack.upserti64' command\0".as_ptr(),
+                    );
                 }
-            }) {
-                Ok(arr) => arr,
-                Err(err) => return err,
+                Ok(at) => at,
             };
-        } else {
-            array = match MsgpackArray::parse(string_dma(key)) {
-                None => return reply_wrong_type(ctx),
-                Some(arr) => arr,
+            i += 2;
+        }
+
+        let Key(key, key_type) = open_rw(ctx, *argv.add(1));
+
+        match check_condition(ctx, key, key_type, &condition) {
+            Err(reply) => return reply,
+            Ok(false) => return RedisModule_ReplyWithNull(ctx),
+            Ok(true) => {}
+        }
+
+        let inserted = match insert_elements(ctx, key, key_type, elements) {
+            Err(reply) => return reply,
+            Ok(inserted) => inserted,
+        };
+        if RedisModule_KeyType(key) == REDISMODULE_KEYTYPE_EMPTY {
+            // nothing to insert into a missing key
+            return RedisModule_ReplyWithLongLong(ctx, 0);
+        }
+
+        let updated_count = inserted.len();
+        if updated_count > 0 {
+            notify_keyspace_event(
+                ctx,
+                REDISMODULE_NOTIFY_STRING,
+                &command_name("upsert"),
+                *argv.add(1),
+            );
+        }
+        if let Some(at) = expire_at {
+            let ttl = at - RedisModule_Milliseconds();
+            if ttl > 0 {
+                RedisModule_SetExpire(key, ttl);
+                notify_keyspace_event(ctx, REDISMODULE_NOTIFY_GENERIC, "expire\0", *argv.add(1));
+            } else {
+                // already expired
+                RedisModule_DeleteKey(key);
+                notify_keyspace_event(ctx, REDISMODULE_NOTIFY_GENERIC, "del\0", *argv.add(1));
+                replicate(ctx, "DEL\0", vec![*argv.add(1)]);
+
+                return RedisModule_ReplyWithLongLong(ctx, if updated_count > 0 { 1 } else { 0 });
             }
         }
 
-        let mut updated_count = 0;
-        for i in 2..argc {
-            let mut ll = 0;
-            if RedisModule_StringToLongLong(*argv.add(i as usize), &mut ll) != REDISMODULE_OK {
-                return REDISMODULE_ERR;
+        // replicate only inserted elements, with absolute expiration time
+        if updated_count > 0 || expire_at.is_some() || key_type == REDISMODULE_KEYTYPE_EMPTY {
+            let mut args = vec![*argv.add(1)];
+            if let Some(at) = expire_at {
+                args.push(create_string(ctx, "PXAT"));
+                args.push(RedisModule_CreateStringFromLongLong(ctx, at));
             }
+            for ll in inserted {
+                args.push(RedisModule_CreateStringFromLongLong(ctx, ll));
+            }
+            replicate(ctx, &command_name("upserti64"), args);
+        }
 
-            let idx_to_insert = match array.binarysearch(Int64(ll)) {
-                SearchResult::Found(_) => continue,
-                SearchResult::NotFound(idx) => idx,
-            };
+        RedisModule_ReplyWithLongLong(ctx, if updated_count > 0 { 1 } else { 0 })
+    })
+}
 
-            match array.insert_at(idx_to_insert, Int64(ll)) {
-                Err(err) => return err,
-                _ => {}
-            };
+/// Upsert integers given as a msgpack array
+///
+/// Elements may be encoded in any integer format. TTL of the key is preserved.
+/// Returns the number of inserted elements, or nil if a condition is not satisfied.
+///
+/// `redis-cli> MSGPACK.UPSERTPACKED key payload [NX|XX] [IFCARD n] [IFHASH h]`
+#[allow(non_snake_case)]
+#[no_mangle]
+pub extern "C" fn UpsertPacked_RedisCommand(
+    ctx: *mut RedisModuleCtx,
+    argv: *mut *mut RedisModuleString,
+    argc: c_int,
+) -> c_int {
+    catch_panic(ctx, || unsafe {
+        RedisModule_AutoMemory(ctx);
+        stats::record_command();
+
+        if argc < 3 {
+            return RedisModule_WrongArity(ctx);
+        }
 
-            updated_count += 1;
+        let mut condition = Condition::default();
+        let mut i = 3;
+        while i < argc as usize {
+            let value = (i + 1 < argc as usize).then(|| string_bytes(*argv.add(i + 1)));
+            match condition.parse(string_bytes(*argv.add(i)), value) {
+                Ok(0) | Err(_) => return reply_syntax_error(ctx),
+                Ok(consumed) => i += consumed,
+            }
         }
 
-        if updated_count > 0 {
-            RedisModule_ReplicateVerbatim(ctx);
+        let elements = match decode::decode_int_array(string_bytes(*argv.add(2))) {
+            Err(Malformed { offset }) => {
+                return RedisModule_ReplyWithError(
+                    ctx,
+                    format!("ERR malformed payload at offset {}\0", offset).as_ptr(),
+                );
+            }
+            Ok(None) => {
+                return RedisModule_ReplyWithError(
+                    ctx,
+                    "ERR payload must be a msgpack array of int64 values\0".as_ptr(),
+                );
+            }
+            Ok(Some(elements)) => elements,
+        };
+
+        let Key(key, key_type) = open_rw(ctx, *argv.add(1));
+
+        match check_condition(ctx, key, key_type, &condition) {
+            Err(reply) => return reply,
+            Ok(false) => return RedisModule_ReplyWithNull(ctx),
+            Ok(true) => {}
         }
 
-        RedisModule_ReplyWithLongLong(ctx, if updated_count > 0 { 1 } else { 0 })
-    }
+        let inserted = match insert_elements(ctx, key, key_type, elements) {
+            Err(reply) => return reply,
+            Ok(inserted) => inserted,
+        };
+        if RedisModule_KeyType(key) == REDISMODULE_KEYTYPE_EMPTY {
+            // nothing to insert into a missing key
+            return RedisModule_ReplyWithLongLong(ctx, 0);
+        }
+
+        if !inserted.is_empty() {
+            notify_keyspace_event(
+                ctx,
+                REDISMODULE_NOTIFY_STRING,
+                &command_name("upsert"),
+                *argv.add(1),
+            );
+        }
+
+        // replicate only inserted elements, packed as well
+        if !inserted.is_empty() || key_type == REDISMODULE_KEYTYPE_EMPTY {
+            let payload = ElementType::I64.encode(&inserted);
+            let args = vec![
+                *argv.add(1),
+                RedisModule_CreateString(ctx, payload.as_ptr(), payload.len()),
+            ];
+            replicate(ctx, &command_name("upsertpacked"), args);
+        }
+
+        RedisModule_ReplyWithLongLong(ctx, inserted.len() as c_longlong)
+    })
 }
 
 /// Delete int64 from array32
 ///
-/// `redis-cli> MSGPACK.DELI64 key [element ...]`
+/// The key is deleted when the array becomes empty unless KEEPEMPTY is specified.
+/// Returns nil without modification if a condition is not satisfied.
+///
+/// `redis-cli> MSGPACK.DELI64 key [KEEPEMPTY] [NX|XX] [IFCARD n] [IFHASH h] [element ...]`
 #[allow(non_snake_case)]
 #[no_mangle]
 pub extern "C" fn DelI64_RedisCommand(
@@ -88,80 +261,2239 @@ pub extern "C" fn DelI64_RedisCommand(
     argv: *mut *mut RedisModuleString,
     argc: c_int,
 ) -> c_int {
-    unsafe {
+    catch_panic(ctx, || unsafe {
         RedisModule_AutoMemory(ctx);
+        stats::record_command();
 
         if argc < 2 {
             return RedisModule_WrongArity(ctx);
         }
 
+        let mut keep_empty = false;
+        let mut condition = Condition::default();
+        let mut elements = vec![];
+        let mut i = 2;
+        while i < argc as usize {
+            if let Some(n) = parse_i64(*argv.add(i)) {
+                elements.push(n);
+                i += 1;
+                continue;
+            }
+            if !elements.is_empty() {
+                return reply_not_integer(ctx);
+            }
+            if is_keep_empty(*argv.add(i)) {
+                keep_empty = true;
+                i += 1;
+                continue;
+            }
+
+            let value = (i + 1 < argc as usize).then(|| string_bytes(*argv.add(i + 1)));
+            match condition.parse(string_bytes(*argv.add(i)), value) {
+                Err(_) => return reply_syntax_error(ctx),
+                Ok(0) => return reply_not_integer(ctx),
+                Ok(consumed) => i += consumed,
+            }
+        }
+
         let Key(key, key_type) = open_rw(ctx, *argv.add(1));
 
-        if key_type == REDISMODULE_KEYTYPE_EMPTY {
-            return RedisModule_ReplyWithLongLong(ctx, 0);
+        match check_condition(ctx, key, key_type, &condition) {
+            Err(reply) => return reply,
+            Ok(false) => return RedisModule_ReplyWithNull(ctx),
+            Ok(true) => {}
+        }
+
+        let mut array = match get_array(key, key_type) {
+            Err(_) => return reply_wrong_type(ctx),
+            Ok(None) => return RedisModule_ReplyWithLongLong(ctx, 0),
+            Ok(Some(arr)) => arr,
+        };
+
+        // elements deleted before hitting malformed bytes are still notified and replicated
+        let mut deleted = vec![];
+        let mut malformed = None;
+        for ll in elements {
+            let idx_to_delete = match array.binarysearch(ll) {
+                Err(err) => {
+                    malformed = Some(err);
+                    break;
+                }
+                Ok(SearchResult::NotFound(_)) => continue,
+                Ok(SearchResult::Found(idx)) => idx,
+            };
+
+            if let Err(err) = array.delete_at(idx_to_delete) {
+                return err;
+            }
+
+            deleted.push(ll);
+        }
+
+        let deleted_count = deleted.len();
+        if deleted_count > 0 {
+            notify_keyspace_event(
+                ctx,
+                REDISMODULE_NOTIFY_STRING,
+                &command_name("del"),
+                *argv.add(1),
+            );
+            if !delete_if_empty(ctx, key, *argv.add(1), array.header().len(), keep_empty) {
+                let mut args = vec![*argv.add(1), create_string(ctx, "KEEPEMPTY")];
+                for ll in deleted {
+                    args.push(RedisModule_CreateStringFromLongLong(ctx, ll));
+                }
+                replicate(ctx, &command_name("deli64"), args);
+            }
+        }
+
+        if let Some(err) = malformed {
+            return reply_malformed(ctx, err);
+        }
+        RedisModule_ReplyWithLongLong(ctx, deleted_count as c_longlong)
+    })
+}
+
+/// Delete int64 elements within the value range [min, max]
+///
+/// `redis-cli> MSGPACK.REMRANGEBYVALUEI64 key [KEEPEMPTY] min max`
+#[allow(non_snake_case)]
+#[no_mangle]
+pub extern "C" fn RemRangeByValueI64_RedisCommand(
+    ctx: *mut RedisModuleCtx,
+    argv: *mut *mut RedisModuleString,
+    argc: c_int,
+) -> c_int {
+    catch_panic(ctx, || unsafe {
+        RedisModule_AutoMemory(ctx);
+        stats::record_command();
+
+        if argc != 4 && argc != 5 {
+            return RedisModule_WrongArity(ctx);
         }
 
-        if key_type != REDISMODULE_KEYTYPE_STRING {
-            return reply_wrong_type(ctx);
+        // KEEPEMPTY comes right after the key as in MSGPACK.DELI64
+        let keep_empty = argc == 5;
+        if keep_empty && !is_keep_empty(*argv.add(2)) {
+            return reply_syntax_error(ctx);
         }
 
-        let mut array: MsgpackArray<RedisDMA, Int64> = match MsgpackArray::parse(string_dma(key)) {
-            None => return reply_wrong_type(ctx),
-            Some(arr) => arr,
+        let (min, max) = match (
+            parse_i64(*argv.add(argc as usize - 2)),
+            parse_i64(*argv.add(argc as usize - 1)),
+        ) {
+            (Some(min), Some(max)) => (min, max),
+            _ => return reply_not_integer(ctx),
         };
 
-        let mut deleted_count = 0;
-        for i in 2..argc {
-            let mut ll = 0;
-            if RedisModule_StringToLongLong(*argv.add(i as usize), &mut ll) != REDISMODULE_OK {
-                return REDISMODULE_ERR;
+        let Key(key, key_type) = open_rw(ctx, *argv.add(1));
+
+        let mut array = match get_array(key, key_type) {
+            Err(_) => return reply_wrong_type(ctx),
+            Ok(None) => return RedisModule_ReplyWithLongLong(ctx, 0),
+            Ok(Some(arr)) => arr,
+        };
+
+        let (start, end) = match (array.lower_bound(min), array.upper_bound(max)) {
+            (Ok(start), Ok(end)) => (start, end),
+            (Err(err), _) | (_, Err(err)) => return reply_malformed(ctx, err),
+        };
+        let deleted_range = match value_range(&array, start, end) {
+            Err(err) => return reply_malformed(ctx, err),
+            Ok(range) => range,
+        };
+
+        let deleted_count = match array.delete_range(start, end) {
+            Err(err) => return err,
+            Ok(n) => n,
+        };
+
+        if let Some(range) = deleted_range {
+            notify_keyspace_event(
+                ctx,
+                REDISMODULE_NOTIFY_STRING,
+                &command_name("remrangebyvalue"),
+                *argv.add(1),
+            );
+            if !delete_if_empty(ctx, key, *argv.add(1), array.header().len(), keep_empty) {
+                replicate_remrange(ctx, *argv.add(1), range);
+            }
+        }
+
+        RedisModule_ReplyWithLongLong(ctx, deleted_count as c_longlong)
+    })
+}
+
+/// Delete int64 elements within the index range [start, stop]
+///
+/// Negative index counts from the end of the array like ZREMRANGEBYRANK.
+///
+/// `redis-cli> MSGPACK.REMRANGEBYINDEXI64 key [KEEPEMPTY] start stop`
+#[allow(non_snake_case)]
+#[no_mangle]
+pub extern "C" fn RemRangeByIndexI64_RedisCommand(
+    ctx: *mut RedisModuleCtx,
+    argv: *mut *mut RedisModuleString,
+    argc: c_int,
+) -> c_int {
+    catch_panic(ctx, || unsafe {
+        RedisModule_AutoMemory(ctx);
+        stats::record_command();
+
+        if argc != 4 && argc != 5 {
+            return RedisModule_WrongArity(ctx);
+        }
+
+        // KEEPEMPTY comes right after the key as in MSGPACK.DELI64
+        let keep_empty = argc == 5;
+        if keep_empty && !is_keep_empty(*argv.add(2)) {
+            return reply_syntax_error(ctx);
+        }
+
+        let (start, stop) = match (
+            parse_i64(*argv.add(argc as usize - 2)),
+            parse_i64(*argv.add(argc as usize - 1)),
+        ) {
+            (Some(start), Some(stop)) => (start, stop),
+            _ => return reply_not_integer(ctx),
+        };
+
+        let Key(key, key_type) = open_rw(ctx, *argv.add(1));
+
+        let mut array = match get_array(key, key_type) {
+            Err(_) => return reply_wrong_type(ctx),
+            Ok(None) => return RedisModule_ReplyWithLongLong(ctx, 0),
+            Ok(Some(arr)) => arr,
+        };
+
+        let (start, end) = index_range(start, stop, array.header().len()).unwrap_or((0, 0));
+        let deleted_range = match value_range(&array, start, end) {
+            Err(err) => return reply_malformed(ctx, err),
+            Ok(range) => range,
+        };
+
+        let deleted_count = match array.delete_range(start, end) {
+            Err(err) => return err,
+            Ok(n) => n,
+        };
+
+        // index may differ between master and replicas in case of inconsistency so
+        // replicate as the value range
+        if let Some(range) = deleted_range {
+            notify_keyspace_event(
+                ctx,
+                REDISMODULE_NOTIFY_STRING,
+                &command_name("remrangebyindex"),
+                *argv.add(1),
+            );
+            if !delete_if_empty(ctx, key, *argv.add(1), array.header().len(), keep_empty) {
+                replicate_remrange(ctx, *argv.add(1), range);
+            }
+        }
+
+        RedisModule_ReplyWithLongLong(ctx, deleted_count as c_longlong)
+    })
+}
+
+/// Get random int64 elements
+///
+/// Returns distinct elements if count is positive, otherwise the same element may be returned multiple times.
+///
+/// `redis-cli> MSGPACK.RANDMEMBERI64 key [count]`
+#[allow(non_snake_case)]
+#[no_mangle]
+pub extern "C" fn RandMemberI64_RedisCommand(
+    ctx: *mut RedisModuleCtx,
+    argv: *mut *mut RedisModuleString,
+    argc: c_int,
+) -> c_int {
+    catch_panic(ctx, || unsafe {
+        RedisModule_AutoMemory(ctx);
+        stats::record_command();
+
+        if argc != 2 && argc != 3 {
+            return RedisModule_WrongArity(ctx);
+        }
+
+        let count = if argc == 3 {
+            match parse_i64(*argv.add(2)) {
+                // same bound as SRANDMEMBER and friends
+                Some(count) if count < -(i64::MAX / 2) => {
+                    return RedisModule_ReplyWithError(ctx, "ERR value is out of range\0".as_ptr());
+                }
+                Some(count) => Some(count),
+                None => return reply_not_integer(ctx),
+            }
+        } else {
+            None
+        };
+
+        let Key(key, key_type) = open_ro(ctx, *argv.add(1));
+
+        let array = match get_array_ro(key, key_type) {
+            Err(_) => return reply_wrong_type(ctx),
+            Ok(None) => {
+                return match count {
+                    None => RedisModule_ReplyWithNull(ctx),
+                    Some(_) => RedisModule_ReplyWithArray(ctx, 0),
+                };
+            }
+            Ok(Some(arr)) => arr,
+        };
+
+        let len = array.header().len();
+        let mut random = random();
+
+        if let Some(count) = count.filter(|&count| count < 0) {
+            // repeated samples are unbounded by the array size, so they are replied one by one
+            // out of all elements read beforehand instead of being collected
+            let elements = match array.to_vec() {
+                Err(err) => return reply_malformed(ctx, err),
+                Ok(elements) => elements,
+            };
+            let count = if len > 0 { -count } else { 0 };
+            RedisModule_ReplyWithArray(ctx, count as c_long);
+            for _ in 0..count {
+                RedisModule_ReplyWithLongLong(ctx, elements[random.below(len)]);
+            }
+            return REDISMODULE_OK;
+        }
+
+        let indices = match count {
+            None => vec![random.below(len)],
+            Some(count) => random.distinct_indices(len, count as usize),
+        };
+
+        // read all elements before replying so that malformed bytes don't break the reply
+        let elements = match indices
+            .into_iter()
+            .map(|idx| array.get(idx))
+            .collect::<Result<Vec<_>, _>>()
+        {
+            Err(err) => return reply_malformed(ctx, err),
+            Ok(elements) => elements.into_iter().flatten().collect::<Vec<_>>(),
+        };
+
+        match count {
+            None => match elements.first() {
+                None => RedisModule_ReplyWithNull(ctx),
+                Some(&n) => RedisModule_ReplyWithLongLong(ctx, n),
+            },
+            Some(_) => {
+                RedisModule_ReplyWithArray(ctx, elements.len() as c_long);
+                for n in elements {
+                    RedisModule_ReplyWithLongLong(ctx, n);
+                }
+                REDISMODULE_OK
+            }
+        }
+    })
+}
+
+/// Incrementally iterate int64 elements
+///
+/// Cursor is "0" to start iteration, otherwise the one returned by previous call, which
+/// points the last returned value rather than the index so that iteration is not affected by
+/// concurrent insertion or deletion.
+/// Returned cursor is "0" when iteration is finished.
+///
+/// `redis-cli> MSGPACK.SCANI64 key cursor [COUNT count] [MIN min] [MAX max]`
+#[allow(non_snake_case)]
+#[no_mangle]
+pub extern "C" fn ScanI64_RedisCommand(
+    ctx: *mut RedisModuleCtx,
+    argv: *mut *mut RedisModuleString,
+    argc: c_int,
+) -> c_int {
+    catch_panic(ctx, || unsafe {
+        RedisModule_AutoMemory(ctx);
+        stats::record_command();
+
+        if argc < 3 || argc % 2 == 0 {
+            return RedisModule_WrongArity(ctx);
+        }
+
+        let after = match string_bytes(*argv.add(2)) {
+            b"0" => None,
+            cursor if cursor.first() == Some(&b'(') => {
+                match std::str::from_utf8(&cursor[1..])
+                    .ok()
+                    .and_then(|c| c.parse().ok())
+                {
+                    Some(n) => Some(n),
+                    None => return reply_invalid_cursor(ctx),
+                }
             }
+            _ => return reply_invalid_cursor(ctx),
+        };
 
-            let idx_to_delete = match array.binarysearch(Int64(ll)) {
-                SearchResult::NotFound(_) => continue,
-                SearchResult::Found(idx) => idx,
+        let mut count = 10;
+        let mut min = None;
+        let mut max = None;
+        for i in (3..argc as usize).step_by(2) {
+            let value = match parse_i64(*argv.add(i + 1)) {
+                Some(value) => value,
+                None => return reply_not_integer(ctx),
             };
+            match string_bytes(*argv.add(i)).to_ascii_uppercase().as_slice() {
+                b"COUNT" if value > 0 => count = value as usize,
+                b"COUNT" => {
+                    return RedisModule_ReplyWithError(
+                        ctx,
+                        "ERR value is out of range, must be positive\0".as_ptr(),
+                    );
+                }
+                b"MIN" => min = Some(value),
+                b"MAX" => max = Some(value),
+                _ => return reply_syntax_error(ctx),
+            }
+        }
+
+        let Key(key, key_type) = open_ro(ctx, *argv.add(1));
+
+        let array = match get_array_ro(key, key_type) {
+            Err(_) => return reply_wrong_type(ctx),
+            Ok(array) => array,
+        };
 
-            match array.delete_at(idx_to_delete) {
-                Err(err) => return err,
-                _ => {}
+        let mut elements = vec![];
+        let mut next = None;
+        if let Some(array) = array {
+            let start = match (after, min) {
+                (Some(after), Some(min)) if after >= min => array.upper_bound(after),
+                (_, Some(min)) => array.lower_bound(min),
+                (Some(after), None) => array.upper_bound(after),
+                (None, None) => Ok(0),
+            };
+            let end = match max {
+                Some(max) => array.upper_bound(max),
+                None => Ok(array.header().len()),
+            };
+            let (start, end) = match (start, end) {
+                (Ok(start), Ok(end)) => (start, end),
+                (Err(err), _) | (_, Err(err)) => return reply_malformed(ctx, err),
             };
 
-            deleted_count += 1;
+            let mut idx = start;
+            while idx < end && elements.len() < count {
+                match array.get(idx) {
+                    Err(err) => return reply_malformed(ctx, err),
+                    Ok(Some(n)) => elements.push(n),
+                    Ok(None) => {}
+                }
+                idx += 1;
+            }
+            if idx < end {
+                next = elements.last().cloned();
+            }
         }
 
-        if deleted_count > 0 {
-            RedisModule_ReplicateVerbatim(ctx);
+        RedisModule_ReplyWithArray(ctx, 2);
+        match next {
+            None => RedisModule_ReplyWithStringBuffer(ctx, "0".as_ptr(), 1),
+            Some(n) => {
+                let cursor = format!("({}", n);
+                RedisModule_ReplyWithStringBuffer(ctx, cursor.as_ptr(), cursor.len())
+            }
+        };
+        RedisModule_ReplyWithArray(ctx, elements.len() as c_long);
+        for n in elements {
+            RedisModule_ReplyWithLongLong(ctx, n);
         }
 
-        RedisModule_ReplyWithLongLong(ctx, deleted_count)
-    }
+        REDISMODULE_OK
+    })
 }
 
-struct Key(*mut RedisModuleKey, c_int);
-
-fn open_rw(ctx: *mut RedisModuleCtx, string: *mut RedisModuleString) -> Key {
-    unsafe {
-        let ptr = RedisModule_OpenKey(ctx, string, REDISMODULE_READ | REDISMODULE_WRITE);
-        let key_type = RedisModule_KeyType(ptr);
+/// Get the minimum int64 element
+///
+/// `redis-cli> MSGPACK.MINI64 key`
+#[allow(non_snake_case)]
+#[no_mangle]
+pub extern "C" fn MinI64_RedisCommand(
+    ctx: *mut RedisModuleCtx,
+    argv: *mut *mut RedisModuleString,
+    argc: c_int,
+) -> c_int {
+    reply_with_quantiles(ctx, argv, argc, Some(0.0))
+}
 
-        Key(ptr, key_type)
-    }
+/// Get the maximum int64 element
+///
+/// `redis-cli> MSGPACK.MAXI64 key`
+#[allow(non_snake_case)]
+#[no_mangle]
+pub extern "C" fn MaxI64_RedisCommand(
+    ctx: *mut RedisModuleCtx,
+    argv: *mut *mut RedisModuleString,
+    argc: c_int,
+) -> c_int {
+    reply_with_quantiles(ctx, argv, argc, Some(1.0))
 }
 
-fn string_dma(key: *mut RedisModuleKey) -> RedisDMA {
-    let mut len: size_t = 0;
-    unsafe {
-        let ptr = RedisModule_StringDMA(key, &mut len, REDISMODULE_WRITE);
-        RedisDMA::wrap(key, ptr, len)
-    }
+/// Get int64 elements at given quantiles by nearest-rank method
+///
+/// `redis-cli> MSGPACK.QUANTILEI64 key quantile [quantile ...]`
+#[allow(non_snake_case)]
+#[no_mangle]
+pub extern "C" fn QuantileI64_RedisCommand(
+    ctx: *mut RedisModuleCtx,
+    argv: *mut *mut RedisModuleString,
+    argc: c_int,
+) -> c_int {
+    reply_with_quantiles(ctx, argv, argc, None)
 }
 
-fn reply_wrong_type(ctx: *mut RedisModuleCtx) -> c_int {
-    unsafe {
+/// Reply with single element at `fixed` quantile if given,
+/// otherwise with the array of elements at quantiles specified in arguments.
+fn reply_with_quantiles(
+    ctx: *mut RedisModuleCtx,
+    argv: *mut *mut RedisModuleString,
+    argc: c_int,
+    fixed: Option<f64>,
+) -> c_int {
+    catch_panic(ctx, || unsafe {
+        RedisModule_AutoMemory(ctx);
+        stats::record_command();
+
+        if (fixed.is_some() && argc != 2) || (fixed.is_none() && argc < 3) {
+            return RedisModule_WrongArity(ctx);
+        }
+
+        let mut quantiles = vec![];
+        for i in 2..argc {
+            let mut q = 0.0;
+            if RedisModule_StringToDouble(*argv.add(i as usize), &mut q) != REDISMODULE_OK
+                || !(0.0..=1.0).contains(&q)
+            {
+                return RedisModule_ReplyWithError(
+                    ctx,
+                    "ERR quantile should be a float between 0 and 1\0".as_ptr(),
+                );
+            }
+            quantiles.push(q);
+        }
+        if let Some(q) = fixed {
+            quantiles.push(q);
+        }
+
+        let Key(key, key_type) = open_ro(ctx, *argv.add(1));
+
+        let array = match get_array_ro(key, key_type) {
+            Err(_) => return reply_wrong_type(ctx),
+            Ok(array) => array,
+        };
+
+        let values = match quantiles
+            .iter()
+            .map(|&q| array.as_ref().map_or(Ok(None), |arr| arr.quantile(q)))
+            .collect::<Result<Vec<_>, _>>()
+        {
+            Err(err) => return reply_malformed(ctx, err),
+            Ok(values) => values,
+        };
+
+        let reply_value = |value| match value {
+            None => RedisModule_ReplyWithNull(ctx),
+            Some(n) => RedisModule_ReplyWithLongLong(ctx, n),
+        };
+
+        match fixed {
+            Some(_) => reply_value(values.first().cloned().flatten()),
+            None => {
+                RedisModule_ReplyWithArray(ctx, values.len() as c_long);
+                for value in values {
+                    reply_value(value);
+                }
+                REDISMODULE_OK
+            }
+        }
+    })
+}
+
+/// Store members of Redis SET or ZSET to msgpack array.
+/// Members must be int64 and destination is overwritten.
+///
+/// `redis-cli> MSGPACK.FROMSET source destination`
+#[allow(non_snake_case)]
+#[no_mangle]
+pub extern "C" fn FromSet_RedisCommand(
+    ctx: *mut RedisModuleCtx,
+    argv: *mut *mut RedisModuleString,
+    argc: c_int,
+) -> c_int {
+    catch_panic(ctx, || unsafe {
+        RedisModule_AutoMemory(ctx);
+        stats::record_command();
+
+        if argc != 3 {
+            return RedisModule_WrongArity(ctx);
+        }
+
+        let Key(source, source_type) = open_ro(ctx, *argv.add(1));
+
+        let mut elements = vec![];
+        match source_type {
+            REDISMODULE_KEYTYPE_EMPTY => {}
+            REDISMODULE_KEYTYPE_SET => {
+                let reply =
+                    RedisModule_Call(ctx, "SMEMBERS\0".as_ptr(), "s\0".as_ptr(), *argv.add(1));
+                if reply.is_null() || RedisModule_CallReplyType(reply) != REDISMODULE_REPLY_ARRAY {
+                    return reply_wrong_type(ctx);
+                }
+                for i in 0..RedisModule_CallReplyLength(reply) {
+                    let mut len: size_t = 0;
+                    let ptr = RedisModule_CallReplyStringPtr(
+                        RedisModule_CallReplyArrayElement(reply, i),
+                        &mut len,
+                    );
+                    match parse_i64_bytes(std::slice::from_raw_parts(ptr, len)) {
+                        Some(n) => elements.push(n),
+                        None => return reply_member_not_integer(ctx),
+                    }
+                }
+            }
+            REDISMODULE_KEYTYPE_ZSET => {
+                RedisModule_ZsetFirstInScoreRange(source, f64::NEG_INFINITY, f64::INFINITY, 0, 0);
+                while RedisModule_ZsetRangeEndReached(source) == 0 {
+                    let member = RedisModule_ZsetRangeCurrentElement(source, std::ptr::null_mut());
+                    match parse_i64(member) {
+                        Some(n) => elements.push(n),
+                        None => {
+                            RedisModule_ZsetRangeStop(source);
+                            return reply_member_not_integer(ctx);
+                        }
+                    }
+                    RedisModule_FreeString(ctx, member);
+                    RedisModule_ZsetRangeNext(source);
+                }
+                RedisModule_ZsetRangeStop(source);
+            }
+            _ => return reply_wrong_type(ctx),
+        }
+        elements.sort();
+        elements.dedup();
+
+        let element_type = config().default_type;
+        if !elements.iter().all(|&n| element_type.fits(n)) {
+            return reply_out_of_range(ctx);
+        }
+        if exceeds_max_elements(elements.len()) {
+            return reply_too_many_elements(ctx);
+        }
+        if exceeds_maxmemory(ctx, element_type.total_bytes(elements.len())) {
+            return reply_oom(ctx);
+        }
+        stats::record_array_len(elements.len());
+
+        let Key(dest, dest_type) = open_rw(ctx, *argv.add(2));
+
+        if dest_type != REDISMODULE_KEYTYPE_EMPTY {
+            RedisModule_DeleteKey(dest);
+        }
+        if !elements.is_empty() {
+            if let Err(err) =
+                Array::with_elements(element_type, |len| allocate(dest, len), &elements)
+            {
+                return err;
+            }
+        }
+
+        replicate_array(ctx, *argv.add(2), dest);
+        if !elements.is_empty() {
+            notify_keyspace_event(
+                ctx,
+                REDISMODULE_NOTIFY_STRING,
+                &command_name("fromset"),
+                *argv.add(2),
+            );
+        } else if dest_type != REDISMODULE_KEYTYPE_EMPTY {
+            notify_keyspace_event(ctx, REDISMODULE_NOTIFY_GENERIC, "del\0", *argv.add(2));
+        }
+
+        RedisModule_ReplyWithLongLong(ctx, elements.len() as c_longlong)
+    })
+}
+
+/// Store elements of msgpack array to Redis SET,
+/// or ZSET with the score same as the member if ZSET is specified.
+/// Destination is overwritten.
+///
+/// Elements beyond ±2^53 are rejected for ZSET, as they can't be distinct double scores.
+///
+/// `redis-cli> MSGPACK.TOSET source destination [ZSET]`
+#[allow(non_snake_case)]
+#[no_mangle]
+pub extern "C" fn ToSet_RedisCommand(
+    ctx: *mut RedisModuleCtx,
+    argv: *mut *mut RedisModuleString,
+    argc: c_int,
+) -> c_int {
+    catch_panic(ctx, || unsafe {
+        RedisModule_AutoMemory(ctx);
+        stats::record_command();
+
+        if argc != 3 && argc != 4 {
+            return RedisModule_WrongArity(ctx);
+        }
+
+        let zset = argc == 4;
+        if zset && !string_bytes(*argv.add(3)).eq_ignore_ascii_case(b"ZSET") {
+            return reply_syntax_error(ctx);
+        }
+
+        let Key(source, source_type) = open_ro(ctx, *argv.add(1));
+
+        let array = match get_array_ro(source, source_type) {
+            Err(_) => return reply_wrong_type(ctx),
+            Ok(array) => array,
+        };
+
+        let mut elements = vec![];
+        if let Some(array) = array {
+            for i in 0..array.header().len() {
+                match array.get(i) {
+                    Err(err) => return reply_malformed(ctx, err),
+                    Ok(Some(n)) => elements.push(n),
+                    Ok(None) => {}
+                }
+            }
+        }
+
+        if zset
+            && !elements
+                .iter()
+                .all(|n| (-MAX_EXACT_SCORE..=MAX_EXACT_SCORE).contains(n))
+        {
+            return RedisModule_ReplyWithError(
+                ctx,
+                "ERR element is out of range for ZSET score\0".as_ptr(),
+            );
+        }
+
+        let Key(dest, dest_type) = open_rw(ctx, *argv.add(2));
+
+        if dest_type != REDISMODULE_KEYTYPE_EMPTY {
+            RedisModule_DeleteKey(dest);
+            replicate(ctx, "DEL\0", vec![*argv.add(2)]);
+        }
+        // members added before a failure are still notified and replicated.
+        // each batch is replicated as ZADD or SADD of its members
+        let mut failure = None;
+        if zset {
+            for chunk in elements.chunks(SADD_BATCH_SIZE) {
+                let mut args = vec![*argv.add(2)];
+                for &n in chunk {
+                    let member = RedisModule_CreateStringFromLongLong(ctx, n);
+                    RedisModule_ZsetAdd(dest, n as c_double, member, std::ptr::null_mut());
+                    // the score is the same as the member
+                    args.push(member);
+                    args.push(member);
+                }
+                replicate(ctx, "ZADD\0", args.clone());
+                for member in args.into_iter().skip(1).step_by(2) {
+                    RedisModule_FreeString(ctx, member);
+                }
+            }
+        } else {
+            // there's no low-level API for SET so we have to call SADD
+            RedisModule_CloseKey(dest);
+            for chunk in elements.chunks(SADD_BATCH_SIZE) {
+                let members = chunk
+                    .iter()
+                    .map(|&n| RedisModule_CreateStringFromLongLong(ctx, n))
+                    .collect::<Vec<_>>();
+                let reply = RedisModule_Call(
+                    ctx,
+                    "SADD\0".as_ptr(),
+                    "sv\0".as_ptr(),
+                    *argv.add(2),
+                    members.as_ptr(),
+                    members.len(),
+                );
+                let added = !reply.is_null()
+                    && RedisModule_CallReplyType(reply) == REDISMODULE_REPLY_INTEGER;
+                if added {
+                    let mut args = vec![*argv.add(2)];
+                    args.extend_from_slice(&members);
+                    replicate(ctx, "SADD\0", args);
+                }
+                for member in members {
+                    RedisModule_FreeString(ctx, member);
+                }
+                if !added {
+                    failure = Some(reply);
+                    break;
+                }
+            }
+        }
+
+        if !elements.is_empty() {
+            notify_keyspace_event(
+                ctx,
+                if zset {
+                    REDISMODULE_NOTIFY_ZSET
+                } else {
+                    REDISMODULE_NOTIFY_SET
+                },
+                &command_name("toset"),
+                *argv.add(2),
+            );
+        } else if dest_type != REDISMODULE_KEYTYPE_EMPTY {
+            notify_keyspace_event(ctx, REDISMODULE_NOTIFY_GENERIC, "del\0", *argv.add(2));
+        }
+
+        if let Some(reply) = failure {
+            return reply_call_error(ctx, reply);
+        }
+        RedisModule_ReplyWithLongLong(ctx, elements.len() as c_longlong)
+    })
+}
+
+/// Get keys whose array contains the int64 element, in the order of arguments
+///
+/// Missing keys are regarded as empty arrays.
+///
+/// `redis-cli> MSGPACK.MEMBEROFI64 element key [key ...]`
+#[allow(non_snake_case)]
+#[no_mangle]
+pub extern "C" fn MemberOfI64_RedisCommand(
+    ctx: *mut RedisModuleCtx,
+    argv: *mut *mut RedisModuleString,
+    argc: c_int,
+) -> c_int {
+    catch_panic(ctx, || unsafe {
+        RedisModule_AutoMemory(ctx);
+        stats::record_command();
+
+        if argc < 3 {
+            return RedisModule_WrongArity(ctx);
+        }
+
+        let element = match parse_i64(*argv.add(1)) {
+            Some(n) => n,
+            None => return reply_not_integer(ctx),
+        };
+
+        // check all keys before replying so that errors don't break the reply
+        let mut members = vec![];
+        for i in 2..argc as usize {
+            let Key(key, key_type) = open_ro(ctx, *argv.add(i));
+            let array = match get_array_ro(key, key_type) {
+                Err(_) => return reply_wrong_type(ctx),
+                Ok(None) => continue,
+                Ok(Some(arr)) => arr,
+            };
+            match array.contains(element) {
+                Err(err) => return reply_malformed(ctx, err),
+                Ok(true) => members.push(*argv.add(i)),
+                Ok(false) => {}
+            }
+        }
+
+        RedisModule_ReplyWithArray(ctx, members.len() as c_long);
+        for key in members {
+            RedisModule_ReplyWithString(ctx, key);
+        }
+
+        REDISMODULE_OK
+    })
+}
+
+/// Get the cardinality and the hash of the array, to be passed to IFCARD and IFHASH
+///
+/// `redis-cli> MSGPACK.VERSION key`
+#[allow(non_snake_case)]
+#[no_mangle]
+pub extern "C" fn Version_RedisCommand(
+    ctx: *mut RedisModuleCtx,
+    argv: *mut *mut RedisModuleString,
+    argc: c_int,
+) -> c_int {
+    catch_panic(ctx, || unsafe {
+        RedisModule_AutoMemory(ctx);
+        stats::record_command();
+
+        if argc != 2 {
+            return RedisModule_WrongArity(ctx);
+        }
+
+        let Key(key, key_type) = open_ro(ctx, *argv.add(1));
+
+        let array = match get_array_ro(key, key_type) {
+            Err(_) => return reply_wrong_type(ctx),
+            Ok(None) => return RedisModule_ReplyWithNull(ctx),
+            Ok(Some(arr)) => arr,
+        };
+
+        let card = array.len();
+        let hash = condition::format_hash(condition::hash(array.into_inner().as_slice()));
+        RedisModule_ReplyWithArray(ctx, 2);
+        RedisModule_ReplyWithLongLong(ctx, card as c_longlong);
+        reply_with_str(ctx, &hash)
+    })
+}
+
+/// Get all int64 elements in ascending order
+///
+/// `redis-cli> MSGPACK.MEMBERSI64 key`
+#[allow(non_snake_case)]
+#[no_mangle]
+pub extern "C" fn MembersI64_RedisCommand(
+    ctx: *mut RedisModuleCtx,
+    argv: *mut *mut RedisModuleString,
+    argc: c_int,
+) -> c_int {
+    catch_panic(ctx, || unsafe {
+        RedisModule_AutoMemory(ctx);
+        stats::record_command();
+
+        if argc != 2 {
+            return RedisModule_WrongArity(ctx);
+        }
+
+        let Key(key, key_type) = open_ro(ctx, *argv.add(1));
+
+        let array = match get_array_ro(key, key_type) {
+            Err(_) => return reply_wrong_type(ctx),
+            Ok(None) => return RedisModule_ReplyWithArray(ctx, 0),
+            Ok(Some(arr)) => arr,
+        };
+
+        // read all elements before replying so that malformed bytes don't break the reply
+        let elements = match array.to_vec() {
+            Err(err) => return reply_malformed(ctx, err),
+            Ok(elements) => elements,
+        };
+
+        RedisModule_ReplyWithArray(ctx, elements.len() as c_long);
+        for n in elements {
+            RedisModule_ReplyWithLongLong(ctx, n);
+        }
+
+        REDISMODULE_OK
+    })
+}
+
+/// Get int64 elements contained in all arrays
+///
+/// `redis-cli> MSGPACK.INTERI64 key [key ...]`
+#[allow(non_snake_case)]
+#[no_mangle]
+pub extern "C" fn InterI64_RedisCommand(
+    ctx: *mut RedisModuleCtx,
+    argv: *mut *mut RedisModuleString,
+    argc: c_int,
+) -> c_int {
+    reply_with_setop(ctx, argv, argc, SetOp::Intersect)
+}
+
+/// Get int64 elements contained in any of arrays
+///
+/// `redis-cli> MSGPACK.UNIONI64 key [key ...]`
+#[allow(non_snake_case)]
+#[no_mangle]
+pub extern "C" fn UnionI64_RedisCommand(
+    ctx: *mut RedisModuleCtx,
+    argv: *mut *mut RedisModuleString,
+    argc: c_int,
+) -> c_int {
+    reply_with_setop(ctx, argv, argc, SetOp::Union)
+}
+
+/// Reply with the result of the set operation over all arrays given in arguments.
+/// Runs on a background thread if the total number of elements reaches BACKGROUND-THRESHOLD.
+fn reply_with_setop(
+    ctx: *mut RedisModuleCtx,
+    argv: *mut *mut RedisModuleString,
+    argc: c_int,
+    op: SetOp,
+) -> c_int {
+    catch_panic(ctx, || unsafe {
+        RedisModule_AutoMemory(ctx);
+        stats::record_command();
+
+        if argc < 2 {
+            return RedisModule_WrongArity(ctx);
+        }
+
+        let mut arrays = vec![];
+        let mut missing = false;
+        for i in 1..argc as usize {
+            let Key(key, key_type) = open_ro(ctx, *argv.add(i));
+            match get_array_ro(key, key_type) {
+                Err(_) => return reply_wrong_type(ctx),
+                Ok(None) => missing = true,
+                Ok(Some(array)) => arrays.push(array),
+            }
+        }
+        if missing && op == SetOp::Intersect {
+            return RedisModule_ReplyWithArray(ctx, 0);
+        }
+
+        let total_elements = arrays.iter().map(Array::len).sum();
+        if background::should_block(ctx, total_elements) {
+            // snapshot raw bytes on the main thread, and decode them on the worker
+            let snapshots = arrays
+                .into_iter()
+                .map(|array| (array.element_type(), array.into_inner().as_slice().to_vec()))
+                .collect::<Vec<_>>();
+            return background::reply_with_elements(ctx, move || {
+                let arrays = snapshots
+                    .into_iter()
+                    .map(|(element_type, bytes)| {
+                        Array::parse(bytes, element_type).ok_or(Malformed { offset: 0 })
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+                op.apply(&arrays)
+            });
+        }
+
+        let elements = match op.apply(&arrays) {
+            Err(err) => return reply_malformed(ctx, err),
+            Ok(elements) => elements,
+        };
+        RedisModule_ReplyWithArray(ctx, elements.len() as c_long);
+        for n in elements {
+            RedisModule_ReplyWithLongLong(ctx, n);
+        }
+
+        REDISMODULE_OK
+    })
+}
+
+/// Copy the array to destination, converting element type if specified
+///
+/// Backing and TTL of the source are copied too.
+/// Destination is not overwritten unless REPLACE is specified.
+/// Returns 1 if copied, 0 otherwise.
+///
+/// `redis-cli> MSGPACK.COPY source destination [TYPE i64|i32] [REPLACE]`
+#[allow(non_snake_case)]
+#[no_mangle]
+pub extern "C" fn Copy_RedisCommand(
+    ctx: *mut RedisModuleCtx,
+    argv: *mut *mut RedisModuleString,
+    argc: c_int,
+) -> c_int {
+    catch_panic(ctx, || unsafe {
+        RedisModule_AutoMemory(ctx);
+        stats::record_command();
+
+        if argc < 3 {
+            return RedisModule_WrongArity(ctx);
+        }
+
+        let mut target_type = None;
+        let mut replace = false;
+        let mut i = 3;
+        while i < argc as usize {
+            let option = string_bytes(*argv.add(i)).to_ascii_uppercase();
+            if option == b"REPLACE" {
+                replace = true;
+                i += 1;
+            } else if option == b"TYPE" && i + 1 < argc as usize {
+                match ElementType::parse(string_bytes(*argv.add(i + 1))) {
+                    Some(element_type) => target_type = Some(element_type),
+                    None => return reply_syntax_error(ctx),
+                }
+                i += 2;
+            } else {
+                return reply_syntax_error(ctx);
+            }
+        }
+
+        if string_bytes(*argv.add(1)) == string_bytes(*argv.add(2)) {
+            return RedisModule_ReplyWithError(
+                ctx,
+                "ERR source and destination objects are the same\0".as_ptr(),
+            );
+        }
+        let Key(source, source_type) = open_ro(ctx, *argv.add(1));
+
+        let array = match get_array_ro(source, source_type) {
+            Err(_) => return reply_wrong_type(ctx),
+            Ok(None) => return RedisModule_ReplyWithLongLong(ctx, 0),
+            Ok(Some(arr)) => arr,
+        };
+        let elements = match array.to_vec() {
+            Err(err) => return reply_malformed(ctx, err),
+            Ok(elements) => elements,
+        };
+        let element_type = target_type.unwrap_or_else(|| array.element_type());
+        let native = array.into_inner().is_native();
+        let ttl = RedisModule_GetExpire(source);
+
+        if !elements.iter().all(|&n| element_type.fits(n)) {
+            return reply_out_of_range(ctx);
+        }
+
+        let Key(dest, dest_type) = open_rw(ctx, *argv.add(2));
+
+        if dest_type != REDISMODULE_KEYTYPE_EMPTY && !replace {
+            return RedisModule_ReplyWithLongLong(ctx, 0);
+        }
+        if exceeds_max_elements(elements.len()) {
+            return reply_too_many_elements(ctx);
+        }
+        if exceeds_maxmemory(ctx, element_type.total_bytes(elements.len())) {
+            return reply_oom(ctx);
+        }
+
+        if let Err(err) = set_array(dest, element_type, native, &elements) {
+            return err;
+        }
+        if ttl != REDISMODULE_NO_EXPIRE {
+            RedisModule_SetExpire(dest, ttl);
+        }
+
+        replicate_array(ctx, *argv.add(2), dest);
+        notify_keyspace_event(
+            ctx,
+            REDISMODULE_NOTIFY_STRING,
+            &command_name("copy"),
+            *argv.add(2),
+        );
+
+        RedisModule_ReplyWithLongLong(ctx, 1)
+    })
+}
+
+/// Merge elements of the source array into destination, converting element type if specified
+///
+/// Element type defaults to that of destination, or source if destination doesn't exist.
+/// Backing and TTL of destination are preserved. Returns the number of elements in destination.
+///
+/// `redis-cli> MSGPACK.MERGE source destination [TYPE i64|i32]`
+#[allow(non_snake_case)]
+#[no_mangle]
+pub extern "C" fn Merge_RedisCommand(
+    ctx: *mut RedisModuleCtx,
+    argv: *mut *mut RedisModuleString,
+    argc: c_int,
+) -> c_int {
+    catch_panic(ctx, || unsafe {
+        RedisModule_AutoMemory(ctx);
+        stats::record_command();
+
+        if argc != 3 && argc != 5 {
+            return RedisModule_WrongArity(ctx);
+        }
+
+        let mut target_type = None;
+        if argc == 5 {
+            if !string_bytes(*argv.add(3)).eq_ignore_ascii_case(b"TYPE") {
+                return reply_syntax_error(ctx);
+            }
+            match ElementType::parse(string_bytes(*argv.add(4))) {
+                Some(element_type) => target_type = Some(element_type),
+                None => return reply_syntax_error(ctx),
+            }
+        }
+
+        // read the source before opening destination, which may be the same key
+        let Key(source, source_type) = open_ro(ctx, *argv.add(1));
+        let (source_element_type, mut elements) = match get_array_ro(source, source_type) {
+            Err(_) => return reply_wrong_type(ctx),
+            Ok(None) => (None, vec![]),
+            Ok(Some(array)) => match array.to_vec() {
+                Err(err) => return reply_malformed(ctx, err),
+                Ok(elements) => (Some(array.element_type()), elements),
+            },
+        };
+
+        let Key(dest, dest_type) = open_rw(ctx, *argv.add(2));
+        let dest_array = match get_array(dest, dest_type) {
+            Err(_) => return reply_wrong_type(ctx),
+            Ok(dest_array) => dest_array,
+        };
+
+        let element_type = target_type
+            .or_else(|| dest_array.as_ref().map(Array::element_type))
+            .or(source_element_type)
+            .unwrap_or(config().default_type);
+        if let Some(array) = &dest_array {
+            match array.to_vec() {
+                Err(err) => return reply_malformed(ctx, err),
+                Ok(mut array_elements) => elements.append(&mut array_elements),
+            }
+        }
+        elements.sort_unstable();
+        elements.dedup();
+
+        let current_len = dest_array.as_ref().map(Array::len);
+        let current_type = dest_array.as_ref().map(Array::element_type);
+        let native = dest_array.is_some_and(|arr| arr.into_inner().is_native());
+        if source_element_type.is_none()
+            || (current_len.is_none() && elements.is_empty())
+            || (current_len == Some(elements.len()) && current_type == Some(element_type))
+        {
+            // nothing to change, and an empty source doesn't create destination
+            return RedisModule_ReplyWithLongLong(ctx, current_len.unwrap_or(0) as c_longlong);
+        }
+
+        if !elements.iter().all(|&n| element_type.fits(n)) {
+            return reply_out_of_range(ctx);
+        }
+        if exceeds_max_elements(elements.len()) {
+            return reply_too_many_elements(ctx);
+        }
+        let current_bytes = match (current_type, current_len) {
+            (Some(current_type), Some(len)) => current_type.total_bytes(len),
+            _ => 0,
+        };
+        let growth = element_type
+            .total_bytes(elements.len())
+            .saturating_sub(current_bytes);
+        if exceeds_maxmemory(ctx, growth) {
+            return reply_oom(ctx);
+        }
+
+        let ttl = RedisModule_GetExpire(dest);
+        if let Err(err) = set_array(dest, element_type, native, &elements) {
+            return err;
+        }
+        if ttl != REDISMODULE_NO_EXPIRE {
+            RedisModule_SetExpire(dest, ttl);
+        }
+        stats::record_array_len(elements.len());
+
+        replicate_array(ctx, *argv.add(2), dest);
+        notify_keyspace_event(
+            ctx,
+            REDISMODULE_NOTIFY_STRING,
+            &command_name("merge"),
+            *argv.add(2),
+        );
+
+        RedisModule_ReplyWithLongLong(ctx, elements.len() as c_longlong)
+    })
+}
+
+/// Get msgpack bytes regardless of the backing
+///
+/// `redis-cli> MSGPACK.GET key`
+#[allow(non_snake_case)]
+#[no_mangle]
+pub extern "C" fn Get_RedisCommand(
+    ctx: *mut RedisModuleCtx,
+    argv: *mut *mut RedisModuleString,
+    argc: c_int,
+) -> c_int {
+    catch_panic(ctx, || unsafe {
+        RedisModule_AutoMemory(ctx);
+        stats::record_command();
+
+        if argc != 2 {
+            return RedisModule_WrongArity(ctx);
+        }
+
+        let Key(key, key_type) = open_ro(ctx, *argv.add(1));
+
+        let buffer = match get_array_ro(key, key_type) {
+            Err(_) => return reply_wrong_type(ctx),
+            Ok(None) => return RedisModule_ReplyWithNull(ctx),
+            Ok(Some(arr)) => arr.into_inner(),
+        };
+
+        let bytes = buffer.as_slice();
+        RedisModule_ReplyWithStringBuffer(ctx, bytes.as_ptr(), bytes.len())
+    })
+}
+
+/// Get msgpack bytes of an array of given elements
+///
+/// Elements are sorted and deduplicated, and always encoded as int64 regardless of DEFAULT-TYPE.
+///
+/// `redis-cli> MSGPACK.ENCODEI64 [element ...]`
+#[allow(non_snake_case)]
+#[no_mangle]
+pub extern "C" fn EncodeI64_RedisCommand(
+    ctx: *mut RedisModuleCtx,
+    argv: *mut *mut RedisModuleString,
+    argc: c_int,
+) -> c_int {
+    catch_panic(ctx, || unsafe {
+        RedisModule_AutoMemory(ctx);
+        stats::record_command();
+
+        let mut elements = vec![];
+        for i in 1..argc as usize {
+            match parse_i64(*argv.add(i)) {
+                Some(n) => elements.push(n),
+                None => return reply_not_integer(ctx),
+            }
+        }
+
+        elements.sort_unstable();
+        elements.dedup();
+
+        let bytes = ElementType::I64.encode(&elements);
+        RedisModule_ReplyWithStringBuffer(ctx, bytes.as_ptr(), bytes.len())
+    })
+}
+
+/// Decode any msgpack value held by the key into type-tagged arrays for inspection
+///
+/// Each value is replied as a pair of its format name and the content.
+///
+/// `redis-cli> MSGPACK.DECODE key`
+#[allow(non_snake_case)]
+#[no_mangle]
+pub extern "C" fn Decode_RedisCommand(
+    ctx: *mut RedisModuleCtx,
+    argv: *mut *mut RedisModuleString,
+    argc: c_int,
+) -> c_int {
+    catch_panic(ctx, || unsafe {
+        RedisModule_AutoMemory(ctx);
+        stats::record_command();
+
+        if argc != 2 {
+            return RedisModule_WrongArity(ctx);
+        }
+
+        let Key(key, key_type) = open_ro(ctx, *argv.add(1));
+
+        let buffer = match key_buffer_ro(key, key_type) {
+            Err(_) => return reply_wrong_type(ctx),
+            Ok(None) => return RedisModule_ReplyWithNull(ctx),
+            Ok(Some(buffer)) => buffer,
+        };
+
+        match decode::decode(buffer.as_slice()) {
+            Err(err) => reply_malformed(ctx, err),
+            Ok(tagged) => reply_with_tagged(ctx, &tagged),
+        }
+    })
+}
+
+/// Convert the backing of msgpack array to native data type or string
+///
+/// `redis-cli> MSGPACK.CONVERT key NATIVE|STRING`
+#[allow(non_snake_case)]
+#[no_mangle]
+pub extern "C" fn Convert_RedisCommand(
+    ctx: *mut RedisModuleCtx,
+    argv: *mut *mut RedisModuleString,
+    argc: c_int,
+) -> c_int {
+    catch_panic(ctx, || unsafe {
+        RedisModule_AutoMemory(ctx);
+        stats::record_command();
+
+        if argc != 3 {
+            return RedisModule_WrongArity(ctx);
+        }
+
+        let native = match string_bytes(*argv.add(2)).to_ascii_uppercase().as_slice() {
+            b"NATIVE" => true,
+            b"STRING" => false,
+            _ => return reply_syntax_error(ctx),
+        };
+
+        let Key(key, key_type) = open_rw(ctx, *argv.add(1));
+
+        let buffer = match get_array(key, key_type) {
+            Err(_) => return reply_wrong_type(ctx),
+            Ok(None) => return RedisModule_ReplyWithError(ctx, "ERR no such key\0".as_ptr()),
+            Ok(Some(arr)) => arr.into_inner(),
+        };
+
+        if buffer.is_native() != native {
+            let bytes = buffer.as_slice().to_vec();
+
+            // replacing the value discards TTL
+            let ttl = RedisModule_GetExpire(key);
+            if native {
+                datatype::set_native(key, &bytes);
+            } else {
+                RedisModule_StringSet(
+                    key,
+                    RedisModule_CreateString(ctx, bytes.as_ptr(), bytes.len()),
+                );
+            }
+            if ttl != REDISMODULE_NO_EXPIRE {
+                RedisModule_SetExpire(key, ttl);
+            }
+
+            // conversion keeps the bytes as is, so the command itself is the effective change
+            replicate(
+                ctx,
+                &command_name("convert"),
+                vec![
+                    *argv.add(1),
+                    create_string(ctx, if native { "NATIVE" } else { "STRING" }),
+                ],
+            );
+            notify_keyspace_event(
+                ctx,
+                REDISMODULE_NOTIFY_GENERIC,
+                &command_name("convert"),
+                *argv.add(1),
+            );
+        }
+
+        RedisModule_ReplyWithSimpleString(ctx, "OK\0".as_ptr())
+    })
+}
+
+/// Check integrity of msgpack bytes held by the key.
+///
+/// Replies flat list of name and value, or nil if the key doesn't exist.
+///
+/// `redis-cli> MSGPACK.VALIDATE key`
+#[allow(non_snake_case)]
+#[no_mangle]
+pub extern "C" fn Validate_RedisCommand(
+    ctx: *mut RedisModuleCtx,
+    argv: *mut *mut RedisModuleString,
+    argc: c_int,
+) -> c_int {
+    reply_with_validation(ctx, argv, argc, false)
+}
+
+/// Same as MSGPACK.VALIDATE, but elements are re-sorted and deduplicated in place if needed.
+///
+/// `redis-cli> MSGPACK.REPAIR key`
+#[allow(non_snake_case)]
+#[no_mangle]
+pub extern "C" fn Repair_RedisCommand(
+    ctx: *mut RedisModuleCtx,
+    argv: *mut *mut RedisModuleString,
+    argc: c_int,
+) -> c_int {
+    reply_with_validation(ctx, argv, argc, true)
+}
+
+/// Reply with the validation result of the key, repairing it if `repair` is true
+fn reply_with_validation(
+    ctx: *mut RedisModuleCtx,
+    argv: *mut *mut RedisModuleString,
+    argc: c_int,
+    repair: bool,
+) -> c_int {
+    catch_panic(ctx, || unsafe {
+        RedisModule_AutoMemory(ctx);
+        stats::record_command();
+
+        if argc != 2 {
+            return RedisModule_WrongArity(ctx);
+        }
+
+        // open for write only when repairing
+        let Key(key, key_type) = if repair {
+            open_rw(ctx, *argv.add(1))
+        } else {
+            open_ro(ctx, *argv.add(1))
+        };
+        let (validation, native) = match key_buffer_ro(key, key_type) {
+            Err(_) => return reply_wrong_type(ctx),
+            Ok(None) => return RedisModule_ReplyWithNull(ctx),
+            Ok(Some(buffer)) => (validate(&buffer), buffer.is_native()),
+        };
+
+        let mut repaired = false;
+        if repair && !validation.is_valid() {
+            let elements = match validation.repaired_elements() {
+                None => {
+                    return RedisModule_ReplyWithError(
+                        ctx,
+                        "ERR malformed array can't be repaired\0".as_ptr(),
+                    );
+                }
+                Some(elements) => elements,
+            };
+            let buffer = match key_buffer(key, key_type) {
+                Ok(Some(buffer)) => buffer,
+                _ => return reply_wrong_type(ctx),
+            };
+            let element_type = validation.element_type.unwrap_or(config().default_type);
+            if let Err(err) =
+                Array::with_elements(element_type, |len| buffer.realloc(len), &elements)
+            {
+                return err;
+            }
+            repaired = true;
+
+            replicate_array(ctx, *argv.add(1), key);
+            notify_keyspace_event(
+                ctx,
+                REDISMODULE_NOTIFY_STRING,
+                &command_name("repair"),
+                *argv.add(1),
+            );
+        }
+
+        let header = match validation.header {
+            None => "invalid",
+            Some(ArrayHeader::Fix(_)) => "fixarray",
+            Some(ArrayHeader::Array16(_)) => "array16",
+            Some(ArrayHeader::Array32(_)) => "array32",
+        };
+        reply_with_fields(
+            ctx,
+            vec![
+                (
+                    "encoding",
+                    Field::Str(if native { "native" } else { "string" }),
+                ),
+                ("header", Field::Str(header)),
+                (
+                    "length",
+                    Field::Int(validation.header.as_ref().map_or(-1, |h| h.len() as i64)),
+                ),
+                (
+                    "element_type",
+                    Field::Str(validation.element_type.map_or("none", ElementType::name)),
+                ),
+                ("sorted", Field::Int(validation.is_sorted() as i64)),
+                ("duplicates", Field::Int(validation.duplicates() as i64)),
+                (
+                    "first_bad_offset",
+                    Field::Int(validation.first_bad_offset.map_or(-1, |o| o as i64)),
+                ),
+                ("valid", Field::Int(validation.is_valid() as i64)),
+                ("repaired", Field::Int(repaired as i64)),
+            ],
+        )
+    })
+}
+
+/// Get module statistics as flat list of name and value
+///
+/// `redis-cli> MSGPACK.STATS`
+#[allow(non_snake_case)]
+#[no_mangle]
+pub extern "C" fn Stats_RedisCommand(
+    ctx: *mut RedisModuleCtx,
+    _argv: *mut *mut RedisModuleString,
+    argc: c_int,
+) -> c_int {
+    catch_panic(ctx, || unsafe {
+        if argc != 1 {
+            return RedisModule_WrongArity(ctx);
+        }
+
+        let fields = stats::snapshot()
+            .into_iter()
+            .map(|(name, value)| (name, Field::Int(value as i64)))
+            .collect();
+        reply_with_fields(ctx, fields)
+    })
+}
+
+/// Run the command body, turning panic into an error reply.
+/// Unwinding across FFI boundary aborts Redis server.
+///
+/// This only keeps the server alive. Handlers parse arguments and validate the array before
+/// replying or mutating, so a panic there leaves nothing behind. A panic after the key is
+/// modified or the reply is started is not rolled back: the key may be partially updated and the
+/// client receives the error in the middle of the reply, so it should close the connection.
+fn catch_panic<F: FnOnce() -> c_int>(ctx: *mut RedisModuleCtx, body: F) -> c_int {
+    match panic::catch_unwind(AssertUnwindSafe(body)) {
+        Ok(ret) => ret,
+        Err(_) => unsafe {
+            RedisModule_ReplyWithError(ctx, "ERR internal error in msgpack module\0".as_ptr())
+        },
+    }
+}
+
+struct Key(*mut RedisModuleKey, c_int);
+
+enum Field {
+    Str(&'static str),
+    Int(i64),
+}
+
+/// Reply with `[format, content]` of the value, recursively for arrays and maps
+fn reply_with_tagged(ctx: *mut RedisModuleCtx, tagged: &Tagged) -> c_int {
+    unsafe {
+        RedisModule_ReplyWithArray(ctx, 2);
+        RedisModule_ReplyWithSimpleString(ctx, format!("{}\0", tagged.format).as_ptr());
+        match &tagged.value {
+            Value::Nil => RedisModule_ReplyWithNull(ctx),
+            Value::Bool(b) => RedisModule_ReplyWithLongLong(ctx, *b as c_longlong),
+            Value::Int(n) => RedisModule_ReplyWithLongLong(ctx, *n),
+            // may not fit in RESP integer
+            Value::UInt(n) => reply_with_str(ctx, &n.to_string()),
+            Value::Float(f) => reply_with_str(ctx, &f.to_string()),
+            Value::Str(bytes) | Value::Bin(bytes) => {
+                RedisModule_ReplyWithStringBuffer(ctx, bytes.as_ptr(), bytes.len())
+            }
+            Value::Array(elements) => {
+                RedisModule_ReplyWithArray(ctx, elements.len() as c_long);
+                for element in elements {
+                    reply_with_tagged(ctx, element);
+                }
+                REDISMODULE_OK
+            }
+            Value::Map(entries) => {
+                RedisModule_ReplyWithArray(ctx, entries.len() as c_long * 2);
+                for (key, value) in entries {
+                    reply_with_tagged(ctx, key);
+                    reply_with_tagged(ctx, value);
+                }
+                REDISMODULE_OK
+            }
+            Value::Ext(ext_type, bytes) => {
+                RedisModule_ReplyWithArray(ctx, 2);
+                RedisModule_ReplyWithLongLong(ctx, *ext_type as c_longlong);
+                RedisModule_ReplyWithStringBuffer(ctx, bytes.as_ptr(), bytes.len())
+            }
+        }
+    }
+}
+
+fn reply_with_str(ctx: *mut RedisModuleCtx, s: &str) -> c_int {
+    unsafe { RedisModule_ReplyWithStringBuffer(ctx, s.as_ptr(), s.len()) }
+}
+
+/// Reply flat list of name and value like CONFIG GET
+fn reply_with_fields(ctx: *mut RedisModuleCtx, fields: Vec<(&str, Field)>) -> c_int {
+    unsafe {
+        RedisModule_ReplyWithArray(ctx, (fields.len() * 2) as c_long);
+        for (name, value) in fields {
+            RedisModule_ReplyWithSimpleString(ctx, format!("{}\0", name).as_ptr());
+            match value {
+                Field::Str(s) => {
+                    RedisModule_ReplyWithSimpleString(ctx, format!("{}\0", s).as_ptr())
+                }
+                Field::Int(n) => RedisModule_ReplyWithLongLong(ctx, n),
+            };
+        }
+
+        REDISMODULE_OK
+    }
+}
+
+fn open_rw(ctx: *mut RedisModuleCtx, string: *mut RedisModuleString) -> Key {
+    unsafe {
+        let ptr = RedisModule_OpenKey(ctx, string, REDISMODULE_READ | REDISMODULE_WRITE);
+        let key_type = RedisModule_KeyType(ptr);
+
+        Key(ptr, key_type)
+    }
+}
+
+/// Open the key for read only. Use `get_array_ro` to read it.
+fn open_ro(ctx: *mut RedisModuleCtx, string: *mut RedisModuleString) -> Key {
+    unsafe {
+        let ptr = RedisModule_OpenKey(ctx, string, REDISMODULE_READ);
+        let key_type = RedisModule_KeyType(ptr);
+
+        Key(ptr, key_type)
+    }
+}
+
+/// Returns true if the array held by the key satisfies the condition, or the reply of the error
+fn check_condition(
+    ctx: *mut RedisModuleCtx,
+    key: *mut RedisModuleKey,
+    key_type: c_int,
+    condition: &Condition,
+) -> Result<bool, c_int> {
+    if condition.is_empty() {
+        return Ok(true);
+    }
+
+    match get_array(key, key_type) {
+        Err(_) => Err(reply_wrong_type(ctx)),
+        Ok(None) => Ok(condition.matches_missing()),
+        Ok(Some(array)) => {
+            let card = array.len();
+            let buffer = array.into_inner();
+            Ok(condition.matches(card, || condition::hash(buffer.as_slice())))
+        }
+    }
+}
+
+/// Insert elements into the array held by the key, creating the array if the key is empty.
+/// The key is not created without elements unless EMPTY-KEY-POLICY is `keep`.
+/// All elements are validated before any modification.
+/// Returns newly inserted elements in ascending order, or the reply of the error.
+fn insert_elements(
+    ctx: *mut RedisModuleCtx,
+    key: *mut RedisModuleKey,
+    key_type: c_int,
+    mut elements: Vec<i64>,
+) -> Result<Vec<i64>, c_int> {
+    let existing = match get_array(key, key_type) {
+        Err(_) => return Err(reply_wrong_type(ctx)),
+        Ok(existing) => existing,
+    };
+
+    // validate all elements before any modification
+    let element_type = existing
+        .as_ref()
+        .map_or(config().default_type, Array::element_type);
+    if !elements.iter().all(|&n| element_type.fits(n)) {
+        return Err(reply_out_of_range(ctx));
+    }
+    elements.sort_unstable();
+    elements.dedup();
+
+    // locate all insert positions first, so that malformed bytes are detected before modification
+    let mut to_insert = vec![];
+    if let Some(array) = &existing {
+        for &n in elements.iter() {
+            match array.binarysearch(n) {
+                Err(err) => return Err(reply_malformed(ctx, err)),
+                Ok(SearchResult::Found(_)) => {}
+                Ok(SearchResult::NotFound(idx)) => to_insert.push((idx, n)),
+            }
+        }
+    } else {
+        to_insert = elements.iter().map(|&n| (0, n)).collect();
+    }
+    if existing.is_none() && to_insert.is_empty() && !config().keep_empty {
+        // don't create an empty key
+        return Ok(vec![]);
+    }
+    let current_len = existing.as_ref().map_or(0, Array::len);
+    let new_len = current_len + to_insert.len();
+    if exceeds_max_elements(new_len) {
+        return Err(reply_too_many_elements(ctx));
+    }
+    let growth = element_type.total_bytes(new_len) - element_type.total_bytes(current_len);
+    if exceeds_maxmemory(ctx, growth) {
+        return Err(reply_oom(ctx));
+    }
+
+    let mut array = match existing {
+        Some(arr) => arr,
+        None => Array::new(element_type, |len| allocate(key, len))?,
+    };
+
+    // grow the buffer once for all elements
+    array.insert_many(&to_insert)?;
+    stats::record_array_len(array.len());
+
+    Ok(to_insert.into_iter().map(|(_, n)| n).collect())
+}
+
+/// Get msgpack array held by the key.
+/// Returns `Ok(None)` if the key is empty, `Err(())` if the key holds other type of value.
+fn get_array(key: *mut RedisModuleKey, key_type: c_int) -> Result<Option<Array>, ()> {
+    parse_array(key_buffer(key, key_type)?)
+}
+
+/// Same as `get_array` for a key opened by `open_ro`
+fn get_array_ro(
+    key: *mut RedisModuleKey,
+    key_type: c_int,
+) -> Result<Option<Array<ReadOnlyBuffer>>, ()> {
+    parse_array(key_buffer_ro(key, key_type)?)
+}
+
+fn parse_array<B: ByteSlice>(buffer: Option<B>) -> Result<Option<Array<B>>, ()> {
+    let buffer = match buffer {
+        None => return Ok(None),
+        Some(buffer) => buffer,
+    };
+
+    let array = Array::parse(buffer, config().default_type).ok_or(())?;
+    stats::record_array_len(array.len());
+
+    Ok(Some(array))
+}
+
+/// Get bytes held by the key without validating them.
+/// Returns `Ok(None)` if the key is empty, `Err(())` if the key is neither string nor native array.
+fn key_buffer(key: *mut RedisModuleKey, key_type: c_int) -> Result<Option<KeyBuffer>, ()> {
+    match key_type {
+        REDISMODULE_KEYTYPE_EMPTY => Ok(None),
+        REDISMODULE_KEYTYPE_STRING => Ok(Some(KeyBuffer::String(string_dma(key)))),
+        REDISMODULE_KEYTYPE_MODULE if datatype::is_native(key) => {
+            Ok(Some(KeyBuffer::Native(datatype::native_buffer(key))))
+        }
+        _ => Err(()),
+    }
+}
+
+/// Same as `key_buffer` for a key opened by `open_ro`
+fn key_buffer_ro(key: *mut RedisModuleKey, key_type: c_int) -> Result<Option<ReadOnlyBuffer>, ()> {
+    match key_type {
+        REDISMODULE_KEYTYPE_EMPTY => Ok(None),
+        REDISMODULE_KEYTYPE_STRING => Ok(Some(ReadOnlyBuffer::String(string_dma_ro(key)))),
+        REDISMODULE_KEYTYPE_MODULE if datatype::is_native(key) => {
+            Ok(Some(ReadOnlyBuffer::Native(datatype::native_buffer(key))))
+        }
+        _ => Err(()),
+    }
+}
+
+/// Allocate buffer for new array on empty key
+fn allocate(key: *mut RedisModuleKey, len: usize) -> Result<KeyBuffer, c_int> {
+    unsafe {
+        let ret = RedisModule_StringTruncate(key, len);
+        if ret != REDISMODULE_OK {
+            Err(ret)
+        } else {
+            Ok(KeyBuffer::String(string_dma(key)))
+        }
+    }
+}
+
+/// Replace the value of the key with an array of given elements, discarding TTL.
+/// Elements must be sorted in ascending order without duplicates and must fit the type.
+fn set_array(
+    key: *mut RedisModuleKey,
+    element_type: ElementType,
+    native: bool,
+    elements: &[i64],
+) -> Result<(), c_int> {
+    unsafe {
+        if RedisModule_KeyType(key) != REDISMODULE_KEYTYPE_EMPTY {
+            RedisModule_DeleteKey(key);
+        }
+        if native {
+            let ret = datatype::set_native(key, &[]);
+            if ret != REDISMODULE_OK {
+                return Err(ret);
+            }
+            let buffer = KeyBuffer::Native(datatype::native_buffer(key));
+            Array::with_elements(element_type, |len| buffer.realloc(len), elements)?;
+        } else {
+            Array::with_elements(element_type, |len| allocate(key, len), elements)?;
+        }
+    }
+    Ok(())
+}
+
+fn string_dma(key: *mut RedisModuleKey) -> RedisDMA {
+    let mut len: size_t = 0;
+    unsafe {
+        let ptr = RedisModule_StringDMA(key, &mut len, REDISMODULE_WRITE);
+        RedisDMA::wrap(key, ptr, len)
+    }
+}
+
+fn string_dma_ro(key: *mut RedisModuleKey) -> ReadOnlyDMA {
+    let mut len: size_t = 0;
+    unsafe {
+        let ptr = RedisModule_StringDMA(key, &mut len, REDISMODULE_READ);
+        ReadOnlyDMA::wrap(ptr, len)
+    }
+}
+
+/// Returns true if MAXELEMENTS is configured and `len` exceeds it
+fn exceeds_max_elements(len: usize) -> bool {
+    let max_elements = config().max_elements;
+    max_elements > 0 && len > max_elements
+}
+
+/// Returns true if growing by `growth` bytes would push used memory over `maxmemory`.
+/// Commands replicated from the master or loaded from AOF are never rejected.
+/// Always false if the server doesn't support (before Redis 6.0).
+///
+/// Used memory ratio is cheap to get, so small growth is checked only against it as deny-oom
+/// does. INFO is queried for exact numbers only if the growth reaches `MAXMEMORY_EXACT_GROWTH`.
+fn exceeds_maxmemory(ctx: *mut RedisModuleCtx, growth: usize) -> bool {
+    unsafe {
+        let flags = match RedisModule_GetContextFlags {
+            Some(get_context_flags) => get_context_flags(ctx),
+            None => return false,
+        };
+        if flags & REDISMODULE_CTX_FLAGS_MAXMEMORY == 0
+            || flags & (REDISMODULE_CTX_FLAGS_REPLICATED | REDISMODULE_CTX_FLAGS_LOADING) != 0
+        {
+            return false;
+        }
+
+        match RedisModule_GetUsedMemoryRatio {
+            Some(get_used_memory_ratio) if get_used_memory_ratio() >= 1.0 => return true,
+            Some(_) => {}
+            None => return false,
+        }
+        if growth < MAXMEMORY_EXACT_GROWTH {
+            return false;
+        }
+
+        let (get_server_info, free_server_info, get_field) = match (
+            RedisModule_GetServerInfo,
+            RedisModule_FreeServerInfo,
+            RedisModule_ServerInfoGetFieldUnsigned,
+        ) {
+            (Some(get), Some(free), Some(field)) => (get, free, field),
+            _ => return false,
+        };
+
+        let info = get_server_info(ctx, "memory\0".as_ptr());
+        if info.is_null() {
+            return false;
+        }
+        let mut used_memory_err: c_int = 0;
+        let mut maxmemory_err: c_int = 0;
+        let used_memory = get_field(info, "used_memory\0".as_ptr(), &mut used_memory_err);
+        let maxmemory = get_field(info, "maxmemory\0".as_ptr(), &mut maxmemory_err);
+        free_server_info(ctx, info);
+
+        used_memory_err == 0
+            && maxmemory_err == 0
+            && maxmemory > 0
+            && used_memory.saturating_add(growth as u64) > maxmemory
+    }
+}
+
+/// Delete the key if the array has no element left, unless `keep_empty` is set
+/// or EMPTY-KEY-POLICY is `keep`.
+/// Deletion is notified and replicated as DEL. Returns true if the key is deleted.
+fn delete_if_empty(
+    ctx: *mut RedisModuleCtx,
+    key: *mut RedisModuleKey,
+    keyname: *mut RedisModuleString,
+    len: usize,
+    keep_empty: bool,
+) -> bool {
+    if len == 0 && !keep_empty && !config().keep_empty {
+        unsafe {
+            RedisModule_DeleteKey(key);
+        }
+        notify_keyspace_event(ctx, REDISMODULE_NOTIFY_GENERIC, "del\0", keyname);
+        replicate(ctx, "DEL\0", vec![keyname]);
+        true
+    } else {
+        false
+    }
+}
+
+/// Returns the first and the last element in [start, end)
+fn value_range(array: &Array, start: usize, end: usize) -> Result<Option<(i64, i64)>, Malformed> {
+    if start >= end {
+        return Ok(None);
+    }
+    match (array.get(start)?, array.get(end - 1)?) {
+        (Some(first), Some(last)) => Ok(Some((first, last))),
+        _ => Ok(None),
+    }
+}
+
+fn replicate_remrange(
+    ctx: *mut RedisModuleCtx,
+    keyname: *mut RedisModuleString,
+    range: (i64, i64),
+) {
+    unsafe {
+        let (first, last) = range;
+        replicate(
+            ctx,
+            &command_name("remrangebyvaluei64"),
+            vec![
+                keyname,
+                create_string(ctx, "KEEPEMPTY"),
+                RedisModule_CreateStringFromLongLong(ctx, first),
+                RedisModule_CreateStringFromLongLong(ctx, last),
+            ],
+        );
+    }
+}
+
+/// Replicate the array held by the key as its resulting state, for changes which can't be
+/// expressed as elements inserted or removed: `SET` of the msgpack bytes followed by CONVERT to
+/// native and `PEXPIREAT` as needed, or `DEL` if the key is empty.
+fn replicate_array(
+    ctx: *mut RedisModuleCtx,
+    keyname: *mut RedisModuleString,
+    key: *mut RedisModuleKey,
+) {
+    unsafe {
+        let buffer = match key_buffer(key, RedisModule_KeyType(key)) {
+            Ok(Some(buffer)) => buffer,
+            _ => return replicate(ctx, "DEL\0", vec![keyname]),
+        };
+
+        let bytes = buffer.as_slice();
+        replicate(
+            ctx,
+            "SET\0",
+            vec![
+                keyname,
+                RedisModule_CreateString(ctx, bytes.as_ptr(), bytes.len()),
+            ],
+        );
+        if buffer.is_native() {
+            replicate(
+                ctx,
+                &command_name("convert"),
+                vec![keyname, create_string(ctx, "NATIVE")],
+            );
+        }
+        let ttl = RedisModule_GetExpire(key);
+        if ttl != REDISMODULE_NO_EXPIRE {
+            replicate(
+                ctx,
+                "PEXPIREAT\0",
+                vec![
+                    keyname,
+                    RedisModule_CreateStringFromLongLong(ctx, RedisModule_Milliseconds() + ttl),
+                ],
+            );
+        }
+    }
+}
+
+/// Replicate the command to replicas and AOF
+fn replicate(ctx: *mut RedisModuleCtx, cmdname: &str, args: Vec<*mut RedisModuleString>) {
+    unsafe {
+        RedisModule_Replicate(
+            ctx,
+            cmdname.as_ptr(),
+            "v\0".as_ptr(),
+            args.as_ptr(),
+            args.len(),
+        );
+    }
+}
+
+fn create_string(ctx: *mut RedisModuleCtx, s: &str) -> *mut RedisModuleString {
+    unsafe { RedisModule_CreateString(ctx, s.as_ptr(), s.len()) }
+}
+
+fn is_keep_empty(arg: *mut RedisModuleString) -> bool {
+    string_bytes(arg).eq_ignore_ascii_case(b"KEEPEMPTY")
+}
+
+/// Fire keyspace event if the server supports it (Redis 6.0 or later)
+fn notify_keyspace_event(
+    ctx: *mut RedisModuleCtx,
+    class: c_int,
+    event: &str,
+    key: *mut RedisModuleString,
+) {
+    unsafe {
+        if let Some(notify) = RedisModule_NotifyKeyspaceEvent {
+            notify(ctx, class, event.as_ptr(), key);
+        }
+    }
+}
+
+fn reply_wrong_type(ctx: *mut RedisModuleCtx) -> c_int {
+    stats::record_wrongtype();
+    unsafe {
         RedisModule_ReplyWithError(
             ctx,
             "WRONGTYPE Key is not a valid msgpack string value.\0".as_ptr(),
         )
     }
 }
+
+fn random() -> Random {
+    // mix wall clock into per-call seed so that successive calls don't share the sequence
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos() as u64)
+        .unwrap_or(0);
+    Random::new(RANDOM_SEED.fetch_add(0x9e37_79b9_7f4a_7c15, Ordering::Relaxed) ^ nanos)
+}
+
+fn reply_not_integer(ctx: *mut RedisModuleCtx) -> c_int {
+    unsafe {
+        RedisModule_ReplyWithError(
+            ctx,
+            "ERR value is not an integer or out of range\0".as_ptr(),
+        )
+    }
+}
+
+fn reply_member_not_integer(ctx: *mut RedisModuleCtx) -> c_int {
+    unsafe {
+        RedisModule_ReplyWithError(
+            ctx,
+            "ERR set member is not an integer or out of range\0".as_ptr(),
+        )
+    }
+}
+
+fn reply_out_of_range(ctx: *mut RedisModuleCtx) -> c_int {
+    unsafe {
+        RedisModule_ReplyWithError(ctx, "ERR value is out of range for element type\0".as_ptr())
+    }
+}
+
+fn reply_oom(ctx: *mut RedisModuleCtx) -> c_int {
+    unsafe {
+        RedisModule_ReplyWithError(
+            ctx,
+            "OOM command not allowed when used memory > 'maxmemory'.\0".as_ptr(),
+        )
+    }
+}
+
+fn reply_too_many_elements(ctx: *mut RedisModuleCtx) -> c_int {
+    unsafe {
+        RedisModule_ReplyWithError(ctx, "ERR number of elements exceeds MAXELEMENTS\0".as_ptr())
+    }
+}
+
+pub fn reply_malformed(ctx: *mut RedisModuleCtx, err: Malformed) -> c_int {
+    unsafe {
+        RedisModule_ReplyWithError(
+            ctx,
+            format!(
+                "ERR malformed msgpack array at offset {}. Check with MSGPACK.VALIDATE\0",
+                err.offset
+            )
+            .as_ptr(),
+        )
+    }
+}
+
+/// Reply with the error of `RedisModule_Call`, or a generic one if it has no error message
+fn reply_call_error(ctx: *mut RedisModuleCtx, reply: *mut RedisModuleCallReply) -> c_int {
+    unsafe {
+        if !reply.is_null() && RedisModule_CallReplyType(reply) == REDISMODULE_REPLY_ERROR {
+            let mut len: size_t = 0;
+            let ptr = RedisModule_CallReplyStringPtr(reply, &mut len);
+            let message = String::from_utf8_lossy(std::slice::from_raw_parts(ptr, len));
+            return RedisModule_ReplyWithError(ctx, format!("{}\0", message).as_ptr());
+        }
+        RedisModule_ReplyWithError(ctx, "ERR failed to call Redis command\0".as_ptr())
+    }
+}
+
+fn reply_syntax_error(ctx: *mut RedisModuleCtx) -> c_int {
+    unsafe { RedisModule_ReplyWithError(ctx, "ERR syntax error\0".as_ptr()) }
+}
+
+fn reply_invalid_cursor(ctx: *mut RedisModuleCtx) -> c_int {
+    unsafe { RedisModule_ReplyWithError(ctx, "ERR invalid cursor\0".as_ptr()) }
+}
+
+fn string_bytes<'a>(string: *mut RedisModuleString) -> &'a [u8] {
+    let mut len: size_t = 0;
+    unsafe {
+        let ptr = RedisModule_StringPtrLen(string, &mut len);
+        std::slice::from_raw_parts(ptr, len)
+    }
+}
+
+fn parse_i64(string: *mut RedisModuleString) -> Option<i64> {
+    let mut ll = 0;
+    unsafe {
+        if RedisModule_StringToLongLong(string, &mut ll) == REDISMODULE_OK {
+            Some(ll)
+        } else {
+            None
+        }
+    }
+}
+
+/// Parse bytes as int64 as strictly as RedisModule_StringToLongLong does
+fn parse_i64_bytes(bytes: &[u8]) -> Option<i64> {
+    let n: i64 = std::str::from_utf8(bytes).ok()?.parse().ok()?;
+    if n.to_string().as_bytes() == bytes {
+        Some(n)
+    } else {
+        None
+    }
+}
+
+/// Convert expiration option into unix time in milliseconds.
+/// Returns `Err(())` if the option is unknown, `Ok(None)` if the value is invalid.
+fn absolute_expire(option: &[u8], value: i64, now: i64) -> Result<Option<i64>, ()> {
+    let (unit, relative) = match option {
+        b"EX" => (1000, true),
+        b"PX" => (1, true),
+        b"EXAT" => (1000, false),
+        b"PXAT" => (1, false),
+        _ => return Err(()),
+    };
+    if value <= 0 {
+        return Ok(None);
+    }
+
+    Ok(value.checked_mul(unit).and_then(|ms| {
+        if relative {
+            ms.checked_add(now)
+        } else {
+            Some(ms)
+        }
+    }))
+}
+
+/// Convert inclusive [start, stop] which may contain negative index into [start, end)
+fn index_range(start: i64, stop: i64, len: usize) -> Option<(usize, usize)> {
+    let len = len as i64;
+    let start = if start < 0 {
+        (len + start).max(0)
+    } else {
+        start
+    };
+    let stop = if stop < 0 {
+        len + stop
+    } else {
+        stop.min(len - 1)
+    };
+
+    if start > stop || start >= len {
+        None
+    } else {
+        Some((start as usize, stop as usize + 1))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{absolute_expire, index_range, parse_i64_bytes};
+
+    #[test]
+    fn test_index_range() {
+        assert_eq!(index_range(0, -1, 5), Some((0, 5)));
+        assert_eq!(index_range(1, 3, 5), Some((1, 4)));
+        assert_eq!(index_range(-2, -1, 5), Some((3, 5)));
+        assert_eq!(index_range(-100, 1, 5), Some((0, 2)));
+        assert_eq!(index_range(2, 100, 5), Some((2, 5)));
+        assert_eq!(index_range(i64::MIN, i64::MAX, 5), Some((0, 5)));
+
+        assert_eq!(index_range(3, 1, 5), None);
+        assert_eq!(index_range(5, 10, 5), None);
+        assert_eq!(index_range(0, -6, 5), None);
+        assert_eq!(index_range(0, i64::MIN, 5), None);
+        assert_eq!(index_range(0, -1, 0), None);
+    }
+    #[test]
+    fn test_parse_i64_bytes() {
+        assert_eq!(parse_i64_bytes(b"0"), Some(0));
+        assert_eq!(parse_i64_bytes(b"-42"), Some(-42));
+        assert_eq!(parse_i64_bytes(b"9223372036854775807"), Some(i64::MAX));
+        assert_eq!(parse_i64_bytes(b"-9223372036854775808"), Some(i64::MIN));
+
+        assert_eq!(parse_i64_bytes(b""), None);
+        assert_eq!(parse_i64_bytes(b"+1"), None);
+        assert_eq!(parse_i64_bytes(b"-0"), None);
+        assert_eq!(parse_i64_bytes(b"01"), None);
+        assert_eq!(parse_i64_bytes(b" 1"), None);
+        assert_eq!(parse_i64_bytes(b"1.0"), None);
+        assert_eq!(parse_i64_bytes(b"9223372036854775808"), None);
+        assert_eq!(parse_i64_bytes(b"-9223372036854775809"), None);
+    }
+    #[test]
+    fn test_absolute_expire() {
+        let now = 1_600_000_000_000;
+        assert_eq!(absolute_expire(b"EX", 10, now), Ok(Some(now + 10_000)));
+        assert_eq!(absolute_expire(b"PX", 10, now), Ok(Some(now + 10)));
+        assert_eq!(
+            absolute_expire(b"EXAT", 1_700_000_000, now),
+            Ok(Some(1_700_000_000_000))
+        );
+        assert_eq!(
+            absolute_expire(b"PXAT", 1_700_000_000_000, now),
+            Ok(Some(1_700_000_000_000))
+        );
+
+        // already expired, left to the caller to delete the key
+        assert_eq!(absolute_expire(b"EXAT", 1, now), Ok(Some(1000)));
+        assert_eq!(absolute_expire(b"PXAT", now - 1, now), Ok(Some(now - 1)));
+
+        assert_eq!(absolute_expire(b"EX", 0, now), Ok(None));
+        assert_eq!(absolute_expire(b"PX", -1, now), Ok(None));
+        assert_eq!(absolute_expire(b"EXAT", i64::MIN, now), Ok(None));
+        assert_eq!(absolute_expire(b"EX", i64::MAX / 1000 + 1, now), Ok(None));
+        assert_eq!(absolute_expire(b"PX", i64::MAX - now + 1, now), Ok(None));
+        assert_eq!(absolute_expire(b"EXAT", i64::MAX, now), Ok(None));
+
+        assert_eq!(absolute_expire(b"KEEPTTL", 1, now), Err(()));
+        assert_eq!(absolute_expire(b"ex", 1, now), Err(()));
+    }
+}
@@ -0,0 +1,136 @@
+//! Preconditions of conditional writes: `NX`, `XX`, `IFCARD n` and `IFHASH h`.
+//!
+//! The hash is 64-bit FNV-1a of the msgpack bytes, exposed by MSGPACK.VERSION as 16 hex digits.
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+pub fn hash(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(FNV_OFFSET_BASIS, |h, &b| {
+        (h ^ b as u64).wrapping_mul(FNV_PRIME)
+    })
+}
+
+pub fn format_hash(hash: u64) -> String {
+    format!("{:016x}", hash)
+}
+
+#[derive(Debug, Default, Eq, PartialEq)]
+pub struct Condition {
+    /// `Some(false)` for NX, `Some(true)` for XX
+    exists: Option<bool>,
+    card: Option<usize>,
+    hash: Option<u64>,
+}
+
+impl Condition {
+    /// Parse a condition option with the argument following it.
+    /// Returns the number of consumed arguments, or 0 if `option` is not a condition.
+    pub fn parse(&mut self, option: &[u8], value: Option<&[u8]>) -> Result<usize, ()> {
+        let option = option.to_ascii_uppercase();
+        match option.as_slice() {
+            b"NX" | b"XX" => {
+                let exists = option == b"XX";
+                if self.exists.is_some_and(|e| e != exists) {
+                    return Err(());
+                }
+                self.exists = Some(exists);
+                Ok(1)
+            }
+            b"IFCARD" => {
+                let card = value
+                    .and_then(|v| std::str::from_utf8(v).ok())
+                    .and_then(|v| v.parse().ok())
+                    .ok_or(())?;
+                self.card = Some(card);
+                Ok(2)
+            }
+            b"IFHASH" => {
+                let hash = value
+                    .filter(|v| v.len() == 16)
+                    .and_then(|v| std::str::from_utf8(v).ok())
+                    .and_then(|v| u64::from_str_radix(v, 16).ok())
+                    .ok_or(())?;
+                self.hash = Some(hash);
+                Ok(2)
+            }
+            _ => Ok(0),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        *self == Condition::default()
+    }
+
+    /// Returns true if the missing key satisfies all the conditions.
+    /// It is regarded as cardinality 0 and never matches IFHASH.
+    pub fn matches_missing(&self) -> bool {
+        self.exists != Some(true) && self.card.unwrap_or(0) == 0 && self.hash.is_none()
+    }
+
+    /// Returns true if the existing array of `card` elements satisfies all the conditions.
+    /// `hash` is evaluated only if IFHASH is given.
+    pub fn matches<F: FnOnce() -> u64>(&self, card: usize, hash: F) -> bool {
+        if self.exists == Some(false) {
+            return false;
+        }
+        if self.card.is_some_and(|expected| expected != card) {
+            return false;
+        }
+        self.hash.map_or(true, |expected| expected == hash())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{format_hash, hash, Condition};
+
+    #[test]
+    fn test_hash() {
+        assert_eq!(hash(b""), 0xcbf29ce484222325);
+        assert_eq!(hash(b"a"), 0xaf63dc4c8601ec8c);
+        assert_eq!(format_hash(0xaf), "00000000000000af");
+    }
+
+    #[test]
+    fn test_parse() {
+        let mut condition = Condition::default();
+        assert!(condition.is_empty());
+        assert_eq!(condition.parse(b"xx", None), Ok(1));
+        assert_eq!(condition.parse(b"XX", None), Ok(1));
+        assert_eq!(condition.parse(b"IFCARD", Some(b"3")), Ok(2));
+        assert_eq!(condition.parse(b"ifhash", Some(b"00000000000000AF")), Ok(2));
+        assert_eq!(condition.parse(b"KEEPTTL", None), Ok(0));
+        assert!(!condition.is_empty());
+
+        assert_eq!(condition.parse(b"NX", None), Err(()));
+        assert_eq!(condition.parse(b"IFCARD", None), Err(()));
+        assert_eq!(condition.parse(b"IFCARD", Some(b"-1")), Err(()));
+        assert_eq!(condition.parse(b"IFHASH", Some(b"af")), Err(()));
+    }
+
+    #[test]
+    fn test_matches() {
+        let mut condition = Condition::default();
+        assert!(condition.matches_missing());
+
+        condition.parse(b"NX", None).unwrap();
+        assert!(condition.matches_missing());
+        assert!(!condition.matches(0, || unreachable!()));
+
+        let mut condition = Condition::default();
+        condition.parse(b"IFCARD", Some(b"0")).unwrap();
+        assert!(condition.matches_missing());
+        assert!(condition.matches(0, || unreachable!()));
+        assert!(!condition.matches(1, || unreachable!()));
+
+        let mut condition = Condition::default();
+        condition.parse(b"XX", None).unwrap();
+        condition
+            .parse(b"IFHASH", Some(b"00000000000000af"))
+            .unwrap();
+        assert!(!condition.matches_missing());
+        assert!(condition.matches(2, || 0xaf));
+        assert!(!condition.matches(2, || 0xae));
+    }
+}
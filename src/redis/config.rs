@@ -0,0 +1,152 @@
+//! Module configuration given as `MODULE LOAD` arguments.
+//!
+//! `MODULE LOAD /path/to/module.so [MAXELEMENTS n] [DEFAULT-TYPE i64|i32] [EMPTY-KEY-POLICY delete|keep] [COMMAND-PREFIX prefix] [BACKGROUND-THRESHOLD n]`
+
+use super::array::ElementType;
+use std::sync::OnceLock;
+
+static CONFIG: OnceLock<Config> = OnceLock::new();
+
+#[derive(Debug, Eq, PartialEq)]
+pub struct Config {
+    /// Upper limit of the number of elements in an array. 0 means unlimited.
+    pub max_elements: usize,
+    /// Element type of newly created arrays
+    pub default_type: ElementType,
+    /// Keep the key holding an empty array instead of deleting it, as if KEEPEMPTY is given
+    pub keep_empty: bool,
+    /// Namespace of commands, e.g. `msgpack` for `MSGPACK.UPSERTI64`
+    pub command_prefix: String,
+    /// Total number of input elements from which set operations run on a background thread.
+    /// 0 means always on the main thread.
+    pub background_threshold: usize,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            max_elements: 0,
+            default_type: ElementType::I64,
+            keep_empty: false,
+            command_prefix: "msgpack".to_string(),
+            background_threshold: 0,
+        }
+    }
+}
+
+impl Config {
+    pub fn parse(args: &[&[u8]]) -> Result<Self, String> {
+        let mut config = Config::default();
+
+        let mut i = 0;
+        while i < args.len() {
+            let name = String::from_utf8_lossy(args[i]).to_ascii_uppercase();
+            let value = match args.get(i + 1) {
+                Some(value) => *value,
+                None => return Err(format!("missing value for {}", name)),
+            };
+
+            match name.as_str() {
+                "MAXELEMENTS" => {
+                    config.max_elements = std::str::from_utf8(value)
+                        .ok()
+                        .and_then(|s| s.parse().ok())
+                        .ok_or_else(|| "MAXELEMENTS must be a non-negative integer".to_string())?;
+                }
+                "DEFAULT-TYPE" => {
+                    config.default_type = ElementType::parse(value)
+                        .ok_or_else(|| "DEFAULT-TYPE must be one of i64, i32".to_string())?;
+                }
+                "EMPTY-KEY-POLICY" => {
+                    config.keep_empty = if value.eq_ignore_ascii_case(b"delete") {
+                        false
+                    } else if value.eq_ignore_ascii_case(b"keep") {
+                        true
+                    } else {
+                        return Err("EMPTY-KEY-POLICY must be one of delete, keep".to_string());
+                    };
+                }
+                "COMMAND-PREFIX" => {
+                    if value.is_empty()
+                        || !value
+                            .iter()
+                            .all(|b| b.is_ascii_alphanumeric() || *b == b'-' || *b == b'_')
+                    {
+                        return Err(
+                            "COMMAND-PREFIX must consist of alphanumerics, '-' or '_'".to_string()
+                        );
+                    }
+                    config.command_prefix = String::from_utf8_lossy(value).to_ascii_lowercase();
+                }
+                "BACKGROUND-THRESHOLD" => {
+                    config.background_threshold = std::str::from_utf8(value)
+                        .ok()
+                        .and_then(|s| s.parse().ok())
+                        .ok_or_else(|| {
+                            "BACKGROUND-THRESHOLD must be a non-negative integer".to_string()
+                        })?;
+                }
+                _ => return Err(format!("unknown option {}", name)),
+            }
+
+            i += 2;
+        }
+
+        Ok(config)
+    }
+}
+
+/// Set the configuration. Should be called once on load.
+pub fn init(config: Config) {
+    let _ = CONFIG.set(config);
+}
+
+pub fn config() -> &'static Config {
+    CONFIG.get_or_init(Config::default)
+}
+
+/// Returns null-terminated full command name, e.g. `msgpack.upserti64\0` for `upserti64`
+pub fn command_name(name: &str) -> String {
+    format!("{}.{}\0", config().command_prefix, name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Config, ElementType};
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(Config::parse(&[]), Ok(Config::default()));
+
+        let config = Config::parse(&[
+            b"MAXELEMENTS",
+            b"1000000",
+            b"default-type",
+            b"i32",
+            b"EMPTY-KEY-POLICY",
+            b"keep",
+            b"COMMAND-PREFIX",
+            b"MP",
+            b"BACKGROUND-THRESHOLD",
+            b"100000",
+        ])
+        .unwrap();
+        assert_eq!(config.max_elements, 1000000);
+        assert_eq!(config.default_type, ElementType::I32);
+        assert!(config.keep_empty);
+        assert_eq!(config.command_prefix, "mp");
+        assert_eq!(config.background_threshold, 100000);
+    }
+
+    #[test]
+    fn test_parse_error() {
+        assert!(Config::parse(&[b"MAXELEMENTS"]).is_err());
+        assert!(Config::parse(&[b"MAXELEMENTS", b"-1"]).is_err());
+        assert!(Config::parse(&[b"DEFAULT-TYPE", b"u8"]).is_err());
+        assert!(Config::parse(&[b"EMPTY-KEY-POLICY", b"never"]).is_err());
+        assert!(Config::parse(&[b"COMMAND-PREFIX", b""]).is_err());
+        assert!(Config::parse(&[b"COMMAND-PREFIX", b"a.b"]).is_err());
+        assert!(Config::parse(&[b"BACKGROUND-THRESHOLD", b"x"]).is_err());
+        assert!(Config::parse(&[b"UNKNOWN", b"1"]).is_err());
+    }
+}
@@ -0,0 +1,258 @@
+//! Native module data type as an alternative backing of msgpack array.
+//!
+//! The value holds exactly the same msgpack bytes as string backing does.
+
+use super::array::ElementType;
+use super::config::command_name;
+use super::*;
+use crate::msgpack::{ByteSlice, ByteVector};
+use libc::{c_int, c_void, size_t};
+use std::mem::size_of;
+use std::ops::{Index, IndexMut};
+use std::ptr::null_mut;
+
+const TYPE_NAME: &str = "msgpk-arr";
+const ENCODING_VERSION: c_int = 0;
+
+static mut MSGPACK_TYPE: *mut RedisModuleType = null_mut();
+
+pub fn create_data_type(ctx: *mut RedisModuleCtx) -> bool {
+    let mut methods = RedisModuleTypeMethods {
+        version: REDISMODULE_TYPE_METHOD_VERSION,
+        rdb_load: Some(rdb_load),
+        rdb_save: Some(rdb_save),
+        aof_rewrite: Some(aof_rewrite),
+        mem_usage: Some(mem_usage),
+        digest: Some(digest),
+        free: Some(free),
+    };
+
+    unsafe {
+        MSGPACK_TYPE = RedisModule_CreateDataType(
+            ctx,
+            format!("{}\0", TYPE_NAME).as_ptr(),
+            ENCODING_VERSION,
+            &mut methods,
+        );
+        !MSGPACK_TYPE.is_null()
+    }
+}
+
+/// Returns true if the key holds native msgpack array
+pub fn is_native(key: *mut RedisModuleKey) -> bool {
+    unsafe { RedisModule_ModuleTypeGetType(key) == MSGPACK_TYPE }
+}
+
+/// Store bytes as native value, replacing current value of the key
+pub fn set_native(key: *mut RedisModuleKey, bytes: &[u8]) -> c_int {
+    let value = NativeValue::create(bytes);
+    let ret = unsafe { RedisModule_ModuleTypeSetValue(key, MSGPACK_TYPE, value as *mut c_void) };
+    if ret != REDISMODULE_OK {
+        NativeValue::destroy(value);
+    }
+    ret
+}
+
+pub fn native_buffer(key: *mut RedisModuleKey) -> NativeBuffer {
+    unsafe {
+        NativeBuffer {
+            value: RedisModule_ModuleTypeGetValue(key) as *mut NativeValue,
+        }
+    }
+}
+
+/// Msgpack bytes of the native value.
+/// Both the value and the bytes are allocated by the Redis allocator, so that they are
+/// accounted in `used_memory` and subject to `maxmemory` as string values are.
+struct NativeValue {
+    ptr: *mut u8,
+    len: usize,
+}
+
+impl NativeValue {
+    /// Allocate a new value holding a copy of the bytes
+    fn create(bytes: &[u8]) -> *mut NativeValue {
+        unsafe {
+            let value = RedisModule_Alloc(size_of::<NativeValue>()) as *mut NativeValue;
+            value.write(NativeValue {
+                ptr: null_mut(),
+                len: 0,
+            });
+            (*value).resize(bytes.len());
+            if !bytes.is_empty() {
+                (*value)
+                    .ptr
+                    .copy_from_nonoverlapping(bytes.as_ptr(), bytes.len());
+            }
+            value
+        }
+    }
+
+    /// Take ownership of bytes allocated by the Redis allocator
+    fn wrap(ptr: *mut u8, len: usize) -> *mut NativeValue {
+        unsafe {
+            let value = RedisModule_Alloc(size_of::<NativeValue>()) as *mut NativeValue;
+            value.write(NativeValue { ptr, len });
+            value
+        }
+    }
+
+    fn as_slice(&self) -> &[u8] {
+        if self.ptr.is_null() {
+            &[]
+        } else {
+            unsafe { std::slice::from_raw_parts(self.ptr, self.len) }
+        }
+    }
+
+    /// Resize the bytes, filling extended part with zero
+    fn resize(&mut self, len: usize) {
+        unsafe {
+            if len == 0 {
+                if !self.ptr.is_null() {
+                    RedisModule_Free(self.ptr as *mut c_void);
+                }
+                self.ptr = null_mut();
+            } else if len != self.len {
+                // Redis allocator never returns null, as it aborts on out of memory
+                self.ptr = RedisModule_Realloc(self.ptr as *mut c_void, len) as *mut u8;
+                if len > self.len {
+                    self.ptr.add(self.len).write_bytes(0, len - self.len);
+                }
+            }
+            self.len = len;
+        }
+    }
+
+    fn destroy(value: *mut NativeValue) {
+        unsafe {
+            (*value).resize(0);
+            RedisModule_Free(value as *mut c_void);
+        }
+    }
+}
+
+/// ByteVector backed by the native value
+pub struct NativeBuffer {
+    value: *mut NativeValue,
+}
+
+impl ByteSlice for NativeBuffer {
+    fn len(&self) -> usize {
+        unsafe { (*self.value).len }
+    }
+}
+
+impl ByteVector for NativeBuffer {
+    type AllocErr = c_int;
+
+    fn memmove(&mut self, dest: usize, src: usize, len: usize) {
+        unsafe {
+            let ptr = (*self.value).ptr;
+            ptr.add(src).copy_to(ptr.add(dest), len)
+        }
+    }
+
+    fn realloc(&self, len: usize) -> Result<Self, Self::AllocErr> {
+        unsafe {
+            (*self.value).resize(len);
+        }
+
+        Ok(NativeBuffer { value: self.value })
+    }
+}
+
+impl NativeBuffer {
+    pub fn as_slice(&self) -> &[u8] {
+        unsafe { (*self.value).as_slice() }
+    }
+}
+
+impl Index<usize> for NativeBuffer {
+    type Output = u8;
+
+    fn index(&self, index: usize) -> &Self::Output {
+        &self.as_slice()[index]
+    }
+}
+
+impl IndexMut<usize> for NativeBuffer {
+    fn index_mut(&mut self, index: usize) -> &mut Self::Output {
+        unsafe {
+            let value = &*self.value;
+            assert!(index < value.len, "index out of bounds");
+            &mut *value.ptr.add(index)
+        }
+    }
+}
+
+extern "C" fn rdb_load(rdb: *mut RedisModuleIO, encver: c_int) -> *mut c_void {
+    if encver != ENCODING_VERSION {
+        return null_mut();
+    }
+
+    unsafe {
+        // the buffer is allocated by the Redis allocator, so it is adopted as is
+        let mut len: size_t = 0;
+        let ptr = RedisModule_LoadStringBuffer(rdb, &mut len);
+        let value = NativeValue::wrap(ptr, len);
+
+        match ElementType::of(&NativeBuffer { value }, ElementType::I64) {
+            None => {
+                NativeValue::destroy(value);
+                null_mut()
+            }
+            Some(_) => value as *mut c_void,
+        }
+    }
+}
+
+extern "C" fn rdb_save(rdb: *mut RedisModuleIO, value: *mut c_void) {
+    unsafe {
+        let bytes = (*(value as *const NativeValue)).as_slice();
+        RedisModule_SaveStringBuffer(rdb, bytes.as_ptr(), bytes.len());
+    }
+}
+
+/// Rewrite as SET of the msgpack bytes followed by CONVERT to native.
+/// Bytes are restored as-is, so the element type is kept and no configuration is applied.
+extern "C" fn aof_rewrite(
+    aof: *mut RedisModuleIO,
+    key: *mut RedisModuleString,
+    value: *mut c_void,
+) {
+    unsafe {
+        let bytes = (*(value as *const NativeValue)).as_slice();
+        RedisModule_EmitAOF(
+            aof,
+            "SET\0".as_ptr(),
+            "sb\0".as_ptr(),
+            key,
+            bytes.as_ptr(),
+            bytes.len(),
+        );
+        RedisModule_EmitAOF(
+            aof,
+            command_name("convert").as_ptr(),
+            "sc\0".as_ptr(),
+            key,
+            "NATIVE\0".as_ptr(),
+        );
+    }
+}
+
+extern "C" fn mem_usage(value: *const c_void) -> size_t {
+    unsafe { size_of::<NativeValue>() + (*(value as *const NativeValue)).len }
+}
+
+extern "C" fn digest(md: *mut RedisModuleDigest, value: *mut c_void) {
+    unsafe {
+        let bytes = (*(value as *const NativeValue)).as_slice();
+        RedisModule_DigestAddStringBuffer(md, bytes.as_ptr(), bytes.len());
+        RedisModule_DigestEndSequence(md);
+    }
+}
+
+extern "C" fn free(value: *mut c_void) {
+    NativeValue::destroy(value as *mut NativeValue);
+}
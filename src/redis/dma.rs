@@ -1,5 +1,5 @@
 use super::*;
-use crate::msgpack::ByteVector;
+use crate::msgpack::{ByteSlice, ByteVector};
 use libc::{c_int, size_t};
 use std::ops::{Index, IndexMut};
 
@@ -9,12 +9,14 @@ pub struct RedisDMA {
     len: size_t,
 }
 
-impl ByteVector for RedisDMA {
-    type AllocErr = c_int;
-
+impl ByteSlice for RedisDMA {
     fn len(&self) -> usize {
         RedisDMA::len(self)
     }
+}
+
+impl ByteVector for RedisDMA {
+    type AllocErr = c_int;
 
     fn memmove(&mut self, dest: usize, src: usize, len: usize) {
         unsafe {
@@ -55,6 +57,10 @@ impl RedisDMA {
     pub fn len(&self) -> usize {
         self.len
     }
+
+    pub fn as_slice(&self) -> &[u8] {
+        unsafe { std::slice::from_raw_parts(self.underlying, self.len) }
+    }
 }
 
 impl Index<usize> for RedisDMA {
@@ -70,3 +76,41 @@ impl IndexMut<usize> for RedisDMA {
         unsafe { &mut *self.underlying.add(index) }
     }
 }
+
+/// DMA of a key opened for read only. Provides no way to mutate the string,
+/// so that reading doesn't unshare it or trigger copy-on-write in forked children.
+pub struct ReadOnlyDMA {
+    underlying: *const u8,
+    len: size_t,
+}
+
+impl ByteSlice for ReadOnlyDMA {
+    fn len(&self) -> usize {
+        ReadOnlyDMA::len(self)
+    }
+}
+
+impl ReadOnlyDMA {
+    pub fn wrap(ptr: *const u8, len: size_t) -> Self {
+        ReadOnlyDMA {
+            underlying: ptr,
+            len,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn as_slice(&self) -> &[u8] {
+        unsafe { std::slice::from_raw_parts(self.underlying, self.len) }
+    }
+}
+
+impl Index<usize> for ReadOnlyDMA {
+    type Output = u8;
+
+    fn index(&self, index: usize) -> &Self::Output {
+        unsafe { &*self.underlying.add(index) }
+    }
+}
@@ -2,11 +2,20 @@
 
 extern crate libc;
 
+mod array;
+mod background;
+mod buffer;
 mod command;
+mod condition;
+mod config;
+mod datatype;
 mod dma;
+mod setop;
+mod stats;
+mod validate;
 
 use command::*;
-use libc::{c_int, c_longlong, size_t};
+use libc::{c_double, c_float, c_int, c_long, c_longlong, c_void, size_t};
 
 const MODULE_NAME: &str = "redis-nativemsgpack";
 const MODULE_VERSION: c_int = 1;
@@ -17,6 +26,29 @@ const REDISMODULE_ERR: c_int = 1;
 
 const REDISMODULE_KEYTYPE_EMPTY: c_int = 0;
 const REDISMODULE_KEYTYPE_STRING: c_int = 1;
+const REDISMODULE_KEYTYPE_SET: c_int = 4;
+const REDISMODULE_KEYTYPE_ZSET: c_int = 5;
+const REDISMODULE_KEYTYPE_MODULE: c_int = 6;
+
+const REDISMODULE_REPLY_ERROR: c_int = 1;
+const REDISMODULE_REPLY_INTEGER: c_int = 2;
+const REDISMODULE_REPLY_ARRAY: c_int = 3;
+
+const REDISMODULE_NOTIFY_GENERIC: c_int = 1 << 2;
+const REDISMODULE_NOTIFY_STRING: c_int = 1 << 3;
+const REDISMODULE_NOTIFY_SET: c_int = 1 << 5;
+const REDISMODULE_NOTIFY_ZSET: c_int = 1 << 7;
+
+const REDISMODULE_CTX_FLAGS_LUA: c_int = 1;
+const REDISMODULE_CTX_FLAGS_MULTI: c_int = 1 << 1;
+const REDISMODULE_CTX_FLAGS_MAXMEMORY: c_int = 1 << 8;
+const REDISMODULE_CTX_FLAGS_REPLICATED: c_int = 1 << 12;
+const REDISMODULE_CTX_FLAGS_LOADING: c_int = 1 << 13;
+const REDISMODULE_CTX_FLAGS_DENY_BLOCKING: c_int = 1 << 21;
+
+const REDISMODULE_NO_EXPIRE: c_longlong = -1;
+
+const REDISMODULE_TYPE_METHOD_VERSION: u64 = 1;
 
 const REDISMODULE_READ: c_int = 1;
 const REDISMODULE_WRITE: c_int = REDISMODULE_READ << 1;
@@ -25,6 +57,14 @@ const REDISMODULE_WRITE: c_int = REDISMODULE_READ << 1;
 pub enum RedisModuleCtx {}
 pub enum RedisModuleString {}
 pub enum RedisModuleKey {}
+pub enum RedisModuleCallReply {}
+pub enum RedisModuleIO {}
+pub enum RedisModuleType {}
+pub enum RedisModuleDigest {}
+pub enum RedisModuleInfoCtx {}
+pub enum RedisModuleBlockedClient {}
+pub enum RedisModuleCommand {}
+pub enum RedisModuleServerInfoData {}
 
 type RedisModuleCmdFunc = extern "C" fn(
     ctx: *mut RedisModuleCtx,
@@ -32,6 +72,19 @@ type RedisModuleCmdFunc = extern "C" fn(
     argc: c_int,
 ) -> c_int;
 
+#[repr(C)]
+pub struct RedisModuleTypeMethods {
+    version: u64,
+    rdb_load: Option<extern "C" fn(rdb: *mut RedisModuleIO, encver: c_int) -> *mut c_void>,
+    rdb_save: Option<extern "C" fn(rdb: *mut RedisModuleIO, value: *mut c_void)>,
+    aof_rewrite: Option<
+        extern "C" fn(aof: *mut RedisModuleIO, key: *mut RedisModuleString, value: *mut c_void),
+    >,
+    mem_usage: Option<extern "C" fn(value: *const c_void) -> size_t>,
+    digest: Option<extern "C" fn(digest: *mut RedisModuleDigest, value: *mut c_void)>,
+    free: Option<extern "C" fn(value: *mut c_void)>,
+}
+
 #[allow(non_upper_case_globals)]
 #[link(name = "redismodule", kind = "static")]
 extern "C" {
@@ -57,21 +110,146 @@ extern "C" {
     static RedisModule_ReplyWithLongLong:
         extern "C" fn(ctx: *mut RedisModuleCtx, ll: c_longlong) -> c_int;
 
+    static RedisModule_ReplyWithArray:
+        extern "C" fn(ctx: *mut RedisModuleCtx, len: c_long) -> c_int;
+
+    static RedisModule_ReplyWithSimpleString:
+        extern "C" fn(ctx: *mut RedisModuleCtx, msg: *const u8) -> c_int;
+
+    static RedisModule_ReplyWithNull: extern "C" fn(ctx: *mut RedisModuleCtx) -> c_int;
+
+    static RedisModule_ReplyWithStringBuffer:
+        extern "C" fn(ctx: *mut RedisModuleCtx, buf: *const u8, len: size_t) -> c_int;
+
+    static RedisModule_ReplyWithString:
+        extern "C" fn(ctx: *mut RedisModuleCtx, str: *mut RedisModuleString) -> c_int;
+
+    static RedisModule_StringPtrLen:
+        extern "C" fn(str: *const RedisModuleString, len: *mut size_t) -> *const u8;
+
     static RedisModule_StringDMA:
         extern "C" fn(key: *mut RedisModuleKey, len: *mut size_t, mode: c_int) -> *mut u8;
 
+    static RedisModule_StringSet:
+        extern "C" fn(key: *mut RedisModuleKey, str: *mut RedisModuleString) -> c_int;
+
     static RedisModule_StringTruncate:
         extern "C" fn(key: *mut RedisModuleKey, newlen: size_t) -> c_int;
 
     static RedisModule_StringToLongLong:
         extern "C" fn(str: *const RedisModuleString, ll: *mut c_longlong) -> c_int;
 
+    static RedisModule_StringToDouble:
+        extern "C" fn(str: *const RedisModuleString, d: *mut c_double) -> c_int;
+
     static RedisModule_OpenKey: extern "C" fn(
         ctx: *mut RedisModuleCtx,
         keyname: *mut RedisModuleString,
         mode: c_int,
     ) -> *mut RedisModuleKey;
 
+    static RedisModule_CloseKey: extern "C" fn(kp: *mut RedisModuleKey);
+
+    static RedisModule_DeleteKey: extern "C" fn(kp: *mut RedisModuleKey) -> c_int;
+
+    static RedisModule_CreateString: extern "C" fn(
+        ctx: *mut RedisModuleCtx,
+        ptr: *const u8,
+        len: size_t,
+    ) -> *mut RedisModuleString;
+
+    static RedisModule_CreateStringFromLongLong:
+        extern "C" fn(ctx: *mut RedisModuleCtx, ll: c_longlong) -> *mut RedisModuleString;
+
+    static RedisModule_FreeString:
+        extern "C" fn(ctx: *mut RedisModuleCtx, str: *mut RedisModuleString);
+
+    static RedisModule_Call: unsafe extern "C" fn(
+        ctx: *mut RedisModuleCtx,
+        cmdname: *const u8,
+        fmt: *const u8,
+        ...
+    ) -> *mut RedisModuleCallReply;
+
+    static RedisModule_CallReplyType: extern "C" fn(reply: *mut RedisModuleCallReply) -> c_int;
+
+    static RedisModule_CallReplyLength: extern "C" fn(reply: *mut RedisModuleCallReply) -> size_t;
+
+    static RedisModule_CallReplyArrayElement:
+        extern "C" fn(reply: *mut RedisModuleCallReply, idx: size_t) -> *mut RedisModuleCallReply;
+
+    static RedisModule_CallReplyStringPtr:
+        extern "C" fn(reply: *mut RedisModuleCallReply, len: *mut size_t) -> *const u8;
+
+    static RedisModule_ZsetAdd: extern "C" fn(
+        key: *mut RedisModuleKey,
+        score: c_double,
+        ele: *mut RedisModuleString,
+        flagsptr: *mut c_int,
+    ) -> c_int;
+
+    static RedisModule_ZsetFirstInScoreRange: extern "C" fn(
+        key: *mut RedisModuleKey,
+        min: c_double,
+        max: c_double,
+        minex: c_int,
+        maxex: c_int,
+    ) -> c_int;
+
+    static RedisModule_ZsetRangeCurrentElement:
+        extern "C" fn(key: *mut RedisModuleKey, score: *mut c_double) -> *mut RedisModuleString;
+
+    static RedisModule_ZsetRangeNext: extern "C" fn(key: *mut RedisModuleKey) -> c_int;
+
+    static RedisModule_ZsetRangeEndReached: extern "C" fn(key: *mut RedisModuleKey) -> c_int;
+
+    static RedisModule_ZsetRangeStop: extern "C" fn(key: *mut RedisModuleKey);
+
+    static RedisModule_GetExpire: extern "C" fn(key: *mut RedisModuleKey) -> c_longlong;
+
+    static RedisModule_SetExpire:
+        extern "C" fn(key: *mut RedisModuleKey, expire: c_longlong) -> c_int;
+
+    static RedisModule_CreateDataType: extern "C" fn(
+        ctx: *mut RedisModuleCtx,
+        name: *const u8,
+        encver: c_int,
+        typemethods: *mut RedisModuleTypeMethods,
+    ) -> *mut RedisModuleType;
+
+    static RedisModule_ModuleTypeSetValue: extern "C" fn(
+        key: *mut RedisModuleKey,
+        mt: *mut RedisModuleType,
+        value: *mut c_void,
+    ) -> c_int;
+
+    static RedisModule_ModuleTypeGetType:
+        extern "C" fn(key: *mut RedisModuleKey) -> *mut RedisModuleType;
+
+    static RedisModule_ModuleTypeGetValue: extern "C" fn(key: *mut RedisModuleKey) -> *mut c_void;
+
+    static RedisModule_SaveStringBuffer:
+        extern "C" fn(io: *mut RedisModuleIO, str: *const u8, len: size_t);
+
+    static RedisModule_LoadStringBuffer:
+        extern "C" fn(io: *mut RedisModuleIO, lenptr: *mut size_t) -> *mut u8;
+
+    static RedisModule_EmitAOF:
+        unsafe extern "C" fn(io: *mut RedisModuleIO, cmdname: *const u8, fmt: *const u8, ...);
+
+    static RedisModule_DigestAddStringBuffer:
+        extern "C" fn(md: *mut RedisModuleDigest, ele: *const u8, len: size_t);
+
+    static RedisModule_DigestEndSequence: extern "C" fn(md: *mut RedisModuleDigest);
+
+    static RedisModule_Alloc: extern "C" fn(bytes: size_t) -> *mut c_void;
+
+    static RedisModule_Realloc: extern "C" fn(ptr: *mut c_void, bytes: size_t) -> *mut c_void;
+
+    static RedisModule_Free: extern "C" fn(ptr: *mut c_void);
+
+    static RedisModule_Milliseconds: extern "C" fn() -> c_longlong;
+
     static RedisModule_AutoMemory: extern "C" fn(ctx: *mut RedisModuleCtx);
 
     static RedisModule_WrongArity: extern "C" fn(ctx: *mut RedisModuleCtx) -> c_int;
@@ -81,11 +259,101 @@ extern "C" {
 
     static RedisModule_KeyType: extern "C" fn(kp: *mut RedisModuleKey) -> c_int;
 
-    static RedisModule_ReplicateVerbatim: extern "C" fn(ctx: *mut RedisModuleCtx) -> c_int;
+    static RedisModule_Log:
+        unsafe extern "C" fn(ctx: *mut RedisModuleCtx, level: *const u8, fmt: *const u8, ...);
+
+    static RedisModule_BlockClient: extern "C" fn(
+        ctx: *mut RedisModuleCtx,
+        reply_callback: Option<RedisModuleCmdFunc>,
+        timeout_callback: Option<RedisModuleCmdFunc>,
+        free_privdata: Option<extern "C" fn(privdata: *mut c_void)>,
+        timeout_ms: c_longlong,
+    ) -> *mut RedisModuleBlockedClient;
+
+    static RedisModule_UnblockClient:
+        extern "C" fn(bc: *mut RedisModuleBlockedClient, privdata: *mut c_void) -> c_int;
+
+    static RedisModule_AbortBlock: extern "C" fn(bc: *mut RedisModuleBlockedClient) -> c_int;
+
+    static RedisModule_GetThreadSafeContext:
+        extern "C" fn(bc: *mut RedisModuleBlockedClient) -> *mut RedisModuleCtx;
+
+    static RedisModule_FreeThreadSafeContext: extern "C" fn(ctx: *mut RedisModuleCtx);
+
+    static RedisModule_Replicate: unsafe extern "C" fn(
+        ctx: *mut RedisModuleCtx,
+        cmdname: *const u8,
+        fmt: *const u8,
+        ...
+    ) -> c_int;
+
+    // Available since Redis 5.0. Null if the server doesn't export.
+    static RedisModule_GetContextFlags: Option<extern "C" fn(ctx: *mut RedisModuleCtx) -> c_int>;
+
+    // Available since Redis 6.0. Null if the server doesn't export.
+    static RedisModule_RegisterInfoFunc: Option<
+        extern "C" fn(
+            ctx: *mut RedisModuleCtx,
+            cb: extern "C" fn(ctx: *mut RedisModuleInfoCtx, for_crash_report: c_int),
+        ) -> c_int,
+    >;
+
+    // Available since Redis 6.0. Null if the server doesn't export.
+    static RedisModule_InfoAddSection:
+        Option<extern "C" fn(ctx: *mut RedisModuleInfoCtx, name: *const u8) -> c_int>;
+
+    // Available since Redis 6.0. Null if the server doesn't export.
+    static RedisModule_InfoAddFieldULongLong:
+        Option<extern "C" fn(ctx: *mut RedisModuleInfoCtx, field: *const u8, value: u64) -> c_int>;
+
+    // Available since Redis 6.0. Null if the server doesn't export.
+    static RedisModule_GetServerInfo: Option<
+        extern "C" fn(
+            ctx: *mut RedisModuleCtx,
+            section: *const u8,
+        ) -> *mut RedisModuleServerInfoData,
+    >;
+
+    // Available since Redis 6.0. Null if the server doesn't export.
+    static RedisModule_FreeServerInfo:
+        Option<extern "C" fn(ctx: *mut RedisModuleCtx, data: *mut RedisModuleServerInfoData)>;
+
+    // Available since Redis 6.0. Null if the server doesn't export.
+    static RedisModule_ServerInfoGetFieldUnsigned: Option<
+        extern "C" fn(
+            data: *mut RedisModuleServerInfoData,
+            field: *const u8,
+            out_err: *mut c_int,
+        ) -> u64,
+    >;
+
+    // Available since Redis 6.0. Null if the server doesn't export.
+    static RedisModule_GetUsedMemoryRatio: Option<extern "C" fn() -> c_float>;
+
+    // Available since Redis 7.0. Null if the server doesn't export.
+    static RedisModule_GetCommand:
+        Option<extern "C" fn(ctx: *mut RedisModuleCtx, name: *const u8) -> *mut RedisModuleCommand>;
+
+    // Available since Redis 7.2. Null if the server doesn't export.
+    static RedisModule_SetCommandACLCategories:
+        Option<extern "C" fn(command: *mut RedisModuleCommand, aclflags: *const u8) -> c_int>;
+
+    // Available since Redis 7.4. Null if the server doesn't export.
+    static RedisModule_AddACLCategory:
+        Option<extern "C" fn(ctx: *mut RedisModuleCtx, name: *const u8) -> c_int>;
+
+    // Available since Redis 6.0. Null if the server doesn't export.
+    static RedisModule_NotifyKeyspaceEvent: Option<
+        extern "C" fn(
+            ctx: *mut RedisModuleCtx,
+            type_: c_int,
+            event: *const u8,
+            key: *mut RedisModuleString,
+        ) -> c_int,
+    >;
 }
 
 #[allow(non_snake_case)]
-#[allow(unused_variables)]
 #[no_mangle]
 extern "C" fn RedisModule_OnLoad(
     ctx: *mut RedisModuleCtx,
@@ -103,25 +371,167 @@ extern "C" fn RedisModule_OnLoad(
             return REDISMODULE_ERR;
         }
 
-        if RedisModule_CreateCommand(
+        let args = (0..argc as usize)
+            .map(|i| {
+                let mut len: size_t = 0;
+                let ptr = RedisModule_StringPtrLen(*argv.add(i), &mut len);
+                std::slice::from_raw_parts(ptr, len)
+            })
+            .collect::<Vec<_>>();
+        match config::Config::parse(&args) {
+            Ok(config) => config::init(config),
+            Err(message) => {
+                RedisModule_Log(
+                    ctx,
+                    "warning\0".as_ptr(),
+                    "%s\0".as_ptr(),
+                    format!("{}\0", message).as_ptr(),
+                );
+                return REDISMODULE_ERR;
+            }
+        }
+
+        if !datatype::create_data_type(ctx) {
+            return REDISMODULE_ERR;
+        }
+
+        if add_acl_category(ctx) != REDISMODULE_OK {
+            return REDISMODULE_ERR;
+        }
+
+        if create_command(
             ctx,
-            "msgpack.upserti64\0".as_ptr(),
+            "upserti64",
             UpsertI64_RedisCommand,
-            "write fast\0".as_ptr(),
+            "write deny-oom fast",
+            1,
             1,
-            -1,
             1,
         ) != REDISMODULE_OK
         {
             return REDISMODULE_ERR;
         }
 
-        if RedisModule_CreateCommand(
+        if create_command(
             ctx,
-            "msgpack.deli64\0".as_ptr(),
-            DelI64_RedisCommand,
-            "write fast\0".as_ptr(),
+            "upsertpacked",
+            UpsertPacked_RedisCommand,
+            "write deny-oom",
             1,
+            1,
+            1,
+        ) != REDISMODULE_OK
+        {
+            return REDISMODULE_ERR;
+        }
+
+        if create_command(ctx, "deli64", DelI64_RedisCommand, "write fast", 1, 1, 1)
+            != REDISMODULE_OK
+        {
+            return REDISMODULE_ERR;
+        }
+
+        if create_command(
+            ctx,
+            "remrangebyvaluei64",
+            RemRangeByValueI64_RedisCommand,
+            "write",
+            1,
+            1,
+            1,
+        ) != REDISMODULE_OK
+        {
+            return REDISMODULE_ERR;
+        }
+
+        if create_command(
+            ctx,
+            "remrangebyindexi64",
+            RemRangeByIndexI64_RedisCommand,
+            "write",
+            1,
+            1,
+            1,
+        ) != REDISMODULE_OK
+        {
+            return REDISMODULE_ERR;
+        }
+
+        if create_command(
+            ctx,
+            "randmemberi64",
+            RandMemberI64_RedisCommand,
+            "readonly random",
+            1,
+            1,
+            1,
+        ) != REDISMODULE_OK
+        {
+            return REDISMODULE_ERR;
+        }
+
+        if create_command(ctx, "scani64", ScanI64_RedisCommand, "readonly", 1, 1, 1)
+            != REDISMODULE_OK
+        {
+            return REDISMODULE_ERR;
+        }
+
+        if create_command(ctx, "mini64", MinI64_RedisCommand, "readonly fast", 1, 1, 1)
+            != REDISMODULE_OK
+        {
+            return REDISMODULE_ERR;
+        }
+
+        if create_command(ctx, "maxi64", MaxI64_RedisCommand, "readonly fast", 1, 1, 1)
+            != REDISMODULE_OK
+        {
+            return REDISMODULE_ERR;
+        }
+
+        if create_command(
+            ctx,
+            "quantilei64",
+            QuantileI64_RedisCommand,
+            "readonly fast",
+            1,
+            1,
+            1,
+        ) != REDISMODULE_OK
+        {
+            return REDISMODULE_ERR;
+        }
+
+        if create_command(
+            ctx,
+            "fromset",
+            FromSet_RedisCommand,
+            "write deny-oom",
+            1,
+            2,
+            1,
+        ) != REDISMODULE_OK
+        {
+            return REDISMODULE_ERR;
+        }
+
+        if create_command(ctx, "toset", ToSet_RedisCommand, "write deny-oom", 1, 2, 1)
+            != REDISMODULE_OK
+        {
+            return REDISMODULE_ERR;
+        }
+
+        if create_command(ctx, "version", Version_RedisCommand, "readonly", 1, 1, 1)
+            != REDISMODULE_OK
+        {
+            return REDISMODULE_ERR;
+        }
+
+        if create_command(
+            ctx,
+            "memberofi64",
+            MemberOfI64_RedisCommand,
+            "readonly",
+            2,
             -1,
             1,
         ) != REDISMODULE_OK
@@ -129,6 +539,158 @@ extern "C" fn RedisModule_OnLoad(
             return REDISMODULE_ERR;
         }
 
+        if create_command(
+            ctx,
+            "membersi64",
+            MembersI64_RedisCommand,
+            "readonly",
+            1,
+            1,
+            1,
+        ) != REDISMODULE_OK
+        {
+            return REDISMODULE_ERR;
+        }
+
+        if create_command(ctx, "interi64", InterI64_RedisCommand, "readonly", 1, -1, 1)
+            != REDISMODULE_OK
+        {
+            return REDISMODULE_ERR;
+        }
+
+        if create_command(ctx, "unioni64", UnionI64_RedisCommand, "readonly", 1, -1, 1)
+            != REDISMODULE_OK
+        {
+            return REDISMODULE_ERR;
+        }
+
+        if create_command(ctx, "copy", Copy_RedisCommand, "write deny-oom", 1, 2, 1)
+            != REDISMODULE_OK
+        {
+            return REDISMODULE_ERR;
+        }
+
+        if create_command(ctx, "merge", Merge_RedisCommand, "write deny-oom", 1, 2, 1)
+            != REDISMODULE_OK
+        {
+            return REDISMODULE_ERR;
+        }
+
+        if create_command(
+            ctx,
+            "encodei64",
+            EncodeI64_RedisCommand,
+            "readonly",
+            0,
+            0,
+            0,
+        ) != REDISMODULE_OK
+        {
+            return REDISMODULE_ERR;
+        }
+
+        if create_command(ctx, "decode", Decode_RedisCommand, "readonly", 1, 1, 1) != REDISMODULE_OK
+        {
+            return REDISMODULE_ERR;
+        }
+
+        if create_command(ctx, "get", Get_RedisCommand, "readonly fast", 1, 1, 1) != REDISMODULE_OK
+        {
+            return REDISMODULE_ERR;
+        }
+
+        if create_command(
+            ctx,
+            "convert",
+            Convert_RedisCommand,
+            "write deny-oom",
+            1,
+            1,
+            1,
+        ) != REDISMODULE_OK
+        {
+            return REDISMODULE_ERR;
+        }
+
+        if create_command(ctx, "validate", Validate_RedisCommand, "readonly", 1, 1, 1)
+            != REDISMODULE_OK
+        {
+            return REDISMODULE_ERR;
+        }
+
+        if create_command(ctx, "repair", Repair_RedisCommand, "write", 1, 1, 1) != REDISMODULE_OK {
+            return REDISMODULE_ERR;
+        }
+
+        if create_command(ctx, "stats", Stats_RedisCommand, "readonly fast", 0, 0, 0)
+            != REDISMODULE_OK
+        {
+            return REDISMODULE_ERR;
+        }
+
+        if stats::register_info_func(ctx) != REDISMODULE_OK {
+            return REDISMODULE_ERR;
+        }
+
+        REDISMODULE_OK
+    }
+}
+
+/// Add ACL category named after the command prefix, e.g. `@msgpack`.
+/// Does nothing if the server doesn't support (before Redis 7.4).
+fn add_acl_category(ctx: *mut RedisModuleCtx) -> c_int {
+    unsafe {
+        match RedisModule_AddACLCategory {
+            Some(add_acl_category) => add_acl_category(
+                ctx,
+                format!("{}\0", config::config().command_prefix).as_ptr(),
+            ),
+            None => REDISMODULE_OK,
+        }
+    }
+}
+
+/// Register the command under the configured prefix and put it in the module's ACL category.
+/// `@read`, `@write`, `@fast` and `@slow` are derived from the flags by the server.
+fn create_command(
+    ctx: *mut RedisModuleCtx,
+    name: &str,
+    cmdfunc: RedisModuleCmdFunc,
+    flags: &str,
+    firstkey: c_int,
+    lastkey: c_int,
+    keystep: c_int,
+) -> c_int {
+    let name = config::command_name(name);
+    unsafe {
+        if RedisModule_CreateCommand(
+            ctx,
+            name.as_ptr(),
+            cmdfunc,
+            format!("{}\0", flags).as_ptr(),
+            firstkey,
+            lastkey,
+            keystep,
+        ) != REDISMODULE_OK
+        {
+            return REDISMODULE_ERR;
+        }
+
+        if let (Some(_), Some(get_command), Some(set_acl_categories)) = (
+            RedisModule_AddACLCategory,
+            RedisModule_GetCommand,
+            RedisModule_SetCommandACLCategories,
+        ) {
+            let command = get_command(ctx, name.as_ptr());
+            if command.is_null() {
+                return REDISMODULE_ERR;
+            }
+            return set_acl_categories(
+                command,
+                format!("{}\0", config::config().command_prefix).as_ptr(),
+            );
+        }
+
         REDISMODULE_OK
     }
 }
@@ -0,0 +1,109 @@
+//! Set operations over sorted, deduplicated elements of msgpack arrays.
+
+use super::array::Array;
+use crate::msgpack::{ByteSlice, Malformed};
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum SetOp {
+    Intersect,
+    Union,
+}
+
+impl SetOp {
+    /// Elements resulting from the operation over arrays, in ascending order
+    pub fn apply<B: ByteSlice>(self, arrays: &[Array<B>]) -> Result<Vec<i64>, Malformed> {
+        match self {
+            SetOp::Intersect => intersect(arrays),
+            SetOp::Union => {
+                let inputs = arrays
+                    .iter()
+                    .map(Array::to_vec)
+                    .collect::<Result<Vec<_>, _>>()?;
+                Ok(union(inputs))
+            }
+        }
+    }
+}
+
+/// Elements contained in all arrays, in ascending order.
+/// Only the smallest array is read through, and the others are binary-searched in place.
+pub fn intersect<B: ByteSlice>(arrays: &[Array<B>]) -> Result<Vec<i64>, Malformed> {
+    let mut arrays = arrays.iter().collect::<Vec<_>>();
+    arrays.sort_by_key(|array| array.len());
+    let (smallest, others) = match arrays.split_first() {
+        None => return Ok(vec![]),
+        Some(split) => split,
+    };
+
+    let mut elements = vec![];
+    for n in smallest.to_vec()? {
+        let mut contained = true;
+        for other in others {
+            if !other.contains(n)? {
+                contained = false;
+                break;
+            }
+        }
+        if contained {
+            elements.push(n);
+        }
+    }
+    Ok(elements)
+}
+
+/// Elements contained in any of inputs, in ascending order.
+/// Merges sorted inputs at once in O(N log k) for k inputs of N elements in total.
+pub fn union(inputs: Vec<Vec<i64>>) -> Vec<i64> {
+    let mut heap = inputs
+        .iter()
+        .enumerate()
+        .filter_map(|(i, input)| input.first().map(|&n| Reverse((n, i, 0))))
+        .collect::<BinaryHeap<_>>();
+
+    let mut elements = Vec::with_capacity(inputs.iter().map(Vec::len).max().unwrap_or(0));
+    while let Some(Reverse((n, i, pos))) = heap.pop() {
+        if elements.last() != Some(&n) {
+            elements.push(n);
+        }
+        if let Some(&next) = inputs[i].get(pos + 1) {
+            heap.push(Reverse((next, i, pos + 1)));
+        }
+    }
+    elements
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::array::{Array, ElementType};
+    use super::{intersect, union};
+
+    fn arrays(inputs: &[&[i64]]) -> Vec<Array<Vec<u8>>> {
+        inputs
+            .iter()
+            .map(|elements| Array::parse(ElementType::I64.encode(elements), ElementType::I64))
+            .collect::<Option<_>>()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_intersect() {
+        assert_eq!(intersect(&arrays(&[])), Ok(Vec::<i64>::new()));
+        assert_eq!(intersect(&arrays(&[&[1, 2, 3]])), Ok(vec![1, 2, 3]));
+        assert_eq!(
+            intersect(&arrays(&[&[1, 2, 3, 5, 8], &[2, 3, 4, 8], &[-1, 2, 8, 9]])),
+            Ok(vec![2, 8])
+        );
+        assert_eq!(intersect(&arrays(&[&[1, 2], &[]])), Ok(Vec::<i64>::new()));
+    }
+
+    #[test]
+    fn test_union() {
+        assert_eq!(union(vec![]), Vec::<i64>::new());
+        assert_eq!(
+            union(vec![vec![1, 3, 5], vec![2, 3, 4], vec![]]),
+            vec![1, 2, 3, 4, 5]
+        );
+    }
+}
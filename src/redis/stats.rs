@@ -0,0 +1,68 @@
+//! Module-wide statistics exposed via MSGPACK.STATS and INFO.
+//!
+//! Commands run on the main thread only, so relaxed ordering is sufficient.
+
+use super::*;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+static COMMANDS: AtomicU64 = AtomicU64::new(0);
+static MEMMOVE_BYTES: AtomicU64 = AtomicU64::new(0);
+static REALLOCS: AtomicU64 = AtomicU64::new(0);
+static WRONGTYPE_ERRORS: AtomicU64 = AtomicU64::new(0);
+static MAX_ARRAY_LEN: AtomicU64 = AtomicU64::new(0);
+
+pub fn record_command() {
+    COMMANDS.fetch_add(1, Ordering::Relaxed);
+}
+
+pub fn record_memmove(len: usize) {
+    MEMMOVE_BYTES.fetch_add(len as u64, Ordering::Relaxed);
+}
+
+pub fn record_realloc() {
+    REALLOCS.fetch_add(1, Ordering::Relaxed);
+}
+
+pub fn record_wrongtype() {
+    WRONGTYPE_ERRORS.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Track the largest array seen so far
+pub fn record_array_len(len: usize) {
+    MAX_ARRAY_LEN.fetch_max(len as u64, Ordering::Relaxed);
+}
+
+/// Returns current values of all counters by name
+pub fn snapshot() -> Vec<(&'static str, u64)> {
+    vec![
+        ("commands_processed", COMMANDS.load(Ordering::Relaxed)),
+        ("memmove_bytes", MEMMOVE_BYTES.load(Ordering::Relaxed)),
+        ("reallocs", REALLOCS.load(Ordering::Relaxed)),
+        ("wrongtype_errors", WRONGTYPE_ERRORS.load(Ordering::Relaxed)),
+        ("max_array_len", MAX_ARRAY_LEN.load(Ordering::Relaxed)),
+    ]
+}
+
+/// Register INFO section. Does nothing if the server doesn't support (before Redis 6.0).
+pub fn register_info_func(ctx: *mut RedisModuleCtx) -> c_int {
+    unsafe {
+        match RedisModule_RegisterInfoFunc {
+            Some(register_info_func) => register_info_func(ctx, info),
+            None => REDISMODULE_OK,
+        }
+    }
+}
+
+extern "C" fn info(ctx: *mut RedisModuleInfoCtx, _for_crash_report: c_int) {
+    unsafe {
+        if let (Some(add_section), Some(add_field)) = (
+            RedisModule_InfoAddSection,
+            RedisModule_InfoAddFieldULongLong,
+        ) {
+            add_section(ctx, "stats\0".as_ptr());
+            for (name, value) in snapshot() {
+                add_field(ctx, format!("{}\0", name).as_ptr(), value);
+            }
+        }
+    }
+}
@@ -0,0 +1,211 @@
+//! Integrity check of msgpack bytes held by a key.
+
+use super::array::ElementType;
+use crate::msgpack::format::{Int32, Int64, Primitive};
+use crate::msgpack::{ArrayHeader, ByteSlice};
+
+pub struct Validation {
+    /// None if the header is malformed
+    pub header: Option<ArrayHeader>,
+    /// Type of the first element. None if the array is empty or the type is unknown
+    pub element_type: Option<ElementType>,
+    /// Elements successfully decoded before `first_bad_offset`
+    pub elements: Vec<i64>,
+    /// Byte offset where the bytes deviate from the format
+    pub first_bad_offset: Option<usize>,
+}
+
+impl Validation {
+    pub fn is_sorted(&self) -> bool {
+        self.elements.windows(2).all(|w| w[0] <= w[1])
+    }
+
+    /// Returns the number of elements which are equal to other element
+    pub fn duplicates(&self) -> usize {
+        let mut elements = self.elements.clone();
+        elements.sort_unstable();
+        elements.windows(2).filter(|w| w[0] == w[1]).count()
+    }
+
+    /// Returns true if the header uses the smallest format for the length
+    pub fn is_canonical_header(&self) -> bool {
+        match &self.header {
+            None => false,
+            Some(header) => *header == ArrayHeader::from_len(header.len()),
+        }
+    }
+
+    pub fn is_valid(&self) -> bool {
+        self.first_bad_offset.is_none()
+            && self.is_canonical_header()
+            && self.is_sorted()
+            && self.duplicates() == 0
+    }
+
+    /// Elements re-sorted and deduplicated, if the bytes are well-formed
+    pub fn repaired_elements(&self) -> Option<Vec<i64>> {
+        if self.first_bad_offset.is_some() {
+            return None;
+        }
+
+        let mut elements = self.elements.clone();
+        elements.sort_unstable();
+        elements.dedup();
+        Some(elements)
+    }
+}
+
+pub fn validate<T: ByteSlice>(bytes: &T) -> Validation {
+    let mut validation = Validation {
+        header: ArrayHeader::parse(bytes),
+        element_type: None,
+        elements: vec![],
+        first_bad_offset: None,
+    };
+
+    let header = match &validation.header {
+        None => {
+            validation.first_bad_offset = Some(0);
+            return validation;
+        }
+        Some(header) => header,
+    };
+
+    let element_type = if header.len() == 0 {
+        None
+    } else if bytes.len() <= header.header_bytes() {
+        validation.first_bad_offset = Some(bytes.len());
+        return validation;
+    } else {
+        match bytes[header.header_bytes()] {
+            Int64::FIRST_BYTE => Some(ElementType::I64),
+            Int32::FIRST_BYTE => Some(ElementType::I32),
+            _ => {
+                validation.first_bad_offset = Some(header.header_bytes());
+                return validation;
+            }
+        }
+    };
+    validation.element_type = element_type;
+
+    let element_bytes = match element_type {
+        Some(ElementType::I64) => Int64::SIZE + 1,
+        Some(ElementType::I32) => Int32::SIZE + 1,
+        None => 0,
+    };
+    for i in 0..header.len() {
+        let offset = header.header_bytes() + i * element_bytes;
+        if offset + element_bytes > bytes.len() {
+            validation.first_bad_offset = Some(offset);
+            return validation;
+        }
+
+        let element = match element_type {
+            Some(ElementType::I64) => Int64::read(bytes, offset).map(|Int64(n)| n),
+            Some(ElementType::I32) => Int32::read(bytes, offset).map(|Int32(n)| n as i64),
+            None => None,
+        };
+        match element {
+            Some(n) => validation.elements.push(n),
+            None => {
+                validation.first_bad_offset = Some(offset);
+                return validation;
+            }
+        }
+    }
+
+    let total_bytes = header.header_bytes() + header.len() * element_bytes;
+    if bytes.len() != total_bytes {
+        validation.first_bad_offset = Some(total_bytes);
+    }
+    validation
+}
+
+#[cfg(test)]
+mod tests {
+    use super::validate;
+    use crate::msgpack::ArrayHeader;
+    use crate::redis::array::ElementType;
+
+    fn i64s(elements: &[i64]) -> Vec<u8> {
+        let mut bytes = vec![0x90 + elements.len() as u8];
+        for n in elements {
+            bytes.push(0xd3);
+            bytes.extend_from_slice(&n.to_be_bytes());
+        }
+        bytes
+    }
+
+    #[test]
+    fn test_valid() {
+        let v = validate(&i64s(&[1, 2, 3]));
+        assert_eq!(v.header, Some(ArrayHeader::Fix(3)));
+        assert_eq!(v.element_type, Some(ElementType::I64));
+        assert_eq!(v.elements, vec![1, 2, 3]);
+        assert_eq!(v.first_bad_offset, None);
+        assert!(v.is_valid());
+
+        let v = validate(&vec![0x90u8]);
+        assert_eq!(v.element_type, None);
+        assert!(v.is_valid());
+
+        let v = validate(&vec![
+            0x92u8, 0xd2, 0, 0, 0, 1, 0xd2, 0xff, 0xff, 0xff, 0xff,
+        ]);
+        assert_eq!(v.element_type, Some(ElementType::I32));
+        assert_eq!(v.elements, vec![1, -1]);
+        assert!(!v.is_sorted());
+    }
+
+    #[test]
+    fn test_unsorted() {
+        let v = validate(&i64s(&[3, 1, 3, 2, 1]));
+        assert_eq!(v.first_bad_offset, None);
+        assert!(!v.is_sorted());
+        assert_eq!(v.duplicates(), 2);
+        assert!(!v.is_valid());
+        assert_eq!(v.repaired_elements(), Some(vec![1, 2, 3]));
+    }
+
+    #[test]
+    fn test_malformed() {
+        // unknown header
+        let v = validate(&vec![0xc0u8]);
+        assert_eq!(v.header, None);
+        assert_eq!(v.first_bad_offset, Some(0));
+        assert_eq!(v.repaired_elements(), None);
+
+        // wrong type byte of the second element
+        let mut bytes = i64s(&[1, 2, 3]);
+        bytes[10] = 0xcc;
+        let v = validate(&bytes);
+        assert_eq!(v.elements, vec![1]);
+        assert_eq!(v.first_bad_offset, Some(10));
+
+        // truncated
+        let bytes = i64s(&[1, 2, 3]);
+        let v = validate(&bytes[..bytes.len() - 1].to_vec());
+        assert_eq!(v.elements, vec![1, 2]);
+        assert_eq!(v.first_bad_offset, Some(19));
+
+        // trailing bytes
+        let mut bytes = i64s(&[1]);
+        bytes.push(0);
+        let v = validate(&bytes);
+        assert_eq!(v.elements, vec![1]);
+        assert_eq!(v.first_bad_offset, Some(10));
+
+        // header only
+        let v = validate(&vec![0x91u8]);
+        assert_eq!(v.first_bad_offset, Some(1));
+
+        // array16 header for short array
+        let mut bytes = vec![0xdcu8, 0, 1];
+        bytes.extend_from_slice(&i64s(&[1])[1..]);
+        let v = validate(&bytes);
+        assert_eq!(v.first_bad_offset, None);
+        assert!(!v.is_canonical_header());
+        assert!(!v.is_valid());
+        assert_eq!(v.repaired_elements(), Some(vec![1]));
+    }
+}